@@ -1,12 +1,16 @@
 use crate::checksum::{Checksum, SumStrBuf};
 
-use async_fetcher::Source;
-use async_std::fs::File;
+use async_fetcher::{metalink::MetalinkError, Source};
+use async_std::fs::{self, File};
 use bytes::BytesMut;
 use futures::prelude::*;
 use futures_codec::{Decoder, FramedRead};
 use serde::Deserialize;
-use std::{convert::TryFrom, io, path::PathBuf};
+use std::{
+    convert::TryFrom,
+    io,
+    path::{Path, PathBuf},
+};
 
 #[derive(Debug, Error)]
 pub enum InputError {
@@ -16,6 +20,14 @@ pub enum InputError {
     Read(#[from] io::Error),
 }
 
+#[derive(Debug, Error)]
+pub enum MetalinkInputError {
+    #[error("invalid metalink document")]
+    Parse(#[from] MetalinkError),
+    #[error("read error")]
+    Read(#[from] io::Error),
+}
+
 #[derive(Default)]
 struct Inputs;
 
@@ -59,9 +71,7 @@ struct Input {
     sum:  Option<SumStrBuf>,
 }
 
-pub fn stream(
-    input: File,
-) -> impl Stream<Item = (Source, Option<Checksum>)> + Send + Unpin {
+pub fn stream(input: File) -> impl Stream<Item = (Source, bool)> + Send + Unpin {
     FramedRead::new(input, Inputs::default())
         .filter_map(|result| async move {
             match result {
@@ -83,7 +93,12 @@ pub fn stream(
                         None => None,
                     };
 
-                    Some((source, sum))
+                    let validated = sum.is_some();
+                    if let Some(sum) = sum {
+                        source = source.checksum(sum);
+                    }
+
+                    Some((source, validated))
                 }
                 Err(InputError::Read(why)) => {
                     epintln!("read error: "(why));
@@ -101,3 +116,26 @@ pub fn stream(
         })
         .boxed()
 }
+
+/// Reads every source described by a Metalink document, to be fetched within `dir`.
+pub async fn metalink(
+    document: &Path,
+    dir: &Path,
+) -> Result<impl Stream<Item = (Source, bool)> + Send + Unpin, MetalinkInputError> {
+    let document = fs::read_to_string(document).await?;
+    let files = async_fetcher::metalink::parse(&document, dir)?;
+
+    // Files may be named within subdirectories, which must exist before they are fetched.
+    for file in &files {
+        if let Some(parent) = file.source.dest.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+    }
+
+    let sources = files.into_iter().map(|file| {
+        let validated = file.source.checksum.is_some();
+        (file.source, validated)
+    });
+
+    Ok(stream::iter(sources))
+}
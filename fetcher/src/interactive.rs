@@ -1,8 +1,8 @@
 use crate::execute;
 
-use async_fetcher::{ChecksumSystem, FetchEvent};
+use async_fetcher::{progress::ProgressTracker, FetchEvent, FetchOutcome, FetchStatus};
 use async_std::task;
-use futures::{channel::mpsc, executor, prelude::*};
+use futures::{channel::mpsc, prelude::*};
 use pbr::{MultiBar, Pipe, ProgressBar, Units};
 use std::{
     collections::HashMap,
@@ -28,8 +28,11 @@ pub fn run(
 
         async move {
             let mut state = HashMap::<Arc<Path>, ProgressBar<Pipe>>::new();
+            let tracker = ProgressTracker::new(Duration::from_secs(1));
 
             while let Some((dest, event)) = erx.next().await {
+                tracker.track(&dest, &event);
+
                 match event {
                     FetchEvent::Progress(written) => {
                         if let Some(bar) = state.get_mut(&dest) {
@@ -37,6 +40,15 @@ pub fn run(
                         }
                     }
 
+                    // What is fetched again is discounted from the progress.
+                    FetchEvent::Retrying { .. } | FetchEvent::PartRetrying { .. } => {
+                        if let (Some(bar), Some(file)) =
+                            (state.get_mut(&dest), tracker.file(&dest))
+                        {
+                            bar.set(file.bytes);
+                        }
+                    }
+
                     FetchEvent::AlreadyFetched => {
                         if let Some(mut bar) = state.remove(&dest) {
                             bar.finish_print(&fomat!("Already fetched "(dest.display())));
@@ -63,10 +75,28 @@ pub fn run(
                         }
                     }
 
+                    FetchEvent::Failed(_) => {
+                        if let Some(mut bar) = state.remove(&dest) {
+                            bar.finish_print(&fomat!("Failed to fetch "(dest.display())));
+                        }
+                    }
+
+                    FetchEvent::Cancelled => {
+                        if let Some(mut bar) = state.remove(&dest) {
+                            bar.finish_print(&fomat!("Cancelled "(dest.display())));
+                        }
+                    }
+
                     _ => (),
                 }
             }
 
+            let totals = tracker.progress();
+            epintln!(
+                "Fetched " (totals.fetched) " files (" (totals.bytes) " bytes) in "
+                (totals.elapsed.as_secs()) "s, " (totals.failed) " failed"
+            );
+
             complete.store(true, Ordering::SeqCst);
         }
     };
@@ -75,38 +105,23 @@ pub fn run(
     let fetch_results = async move {
         while let Some((dest, result)) = fetch_rx.next().await {
             match result {
-                Ok(false) => epintln!((dest.display()) " was successfully fetched"),
-                Ok(true) => epintln!((dest.display()) " is now validating"),
+                Ok((FetchOutcome { status, .. }, validated)) => match status {
+                    FetchStatus::AlreadyFetched | FetchStatus::NotModified => {
+                        epintln!((dest.display()) " is already up to date")
+                    }
+                    _ if validated => {
+                        epintln!((dest.display()) " was successfully validated")
+                    }
+                    _ => epintln!((dest.display()) " was successfully fetched"),
+                },
                 Err(why) => epintln!((dest.display()) " failed: " [why]),
             }
         }
     };
 
-    let (sum_tx, sum_rx) = mpsc::channel::<(Arc<Path>, _)>(0);
-    let sum_results = async move {
-        let mut stream = ChecksumSystem::new()
-            .build(sum_rx)
-            // Distribute each checksum future across a thread pool.
-            .map(|future| task::spawn_blocking(|| executor::block_on(future)))
-            // Limiting up to 32 concurrent tasks at a time.
-            .buffer_unordered(32);
-
-        while let Some((dest, result)) = stream.next().await {
-            match result {
-                Ok(()) => epintln!((dest.display()) " was successfully validated"),
-                Err(why) => epintln!((dest.display()) " failed to validate: " [why]),
-            }
-        }
-    };
-
     let handle = thread::spawn(|| {
         task::block_on(async move {
-            join!(
-                fetcher,
-                fetch_results,
-                sum_results,
-                execute(etx, fetch_tx, sum_tx).boxed_local(),
-            )
+            join!(fetcher, fetch_results, execute(etx, fetch_tx).boxed_local())
         })
     });
 
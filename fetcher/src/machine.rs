@@ -1,15 +1,13 @@
 use crate::execute;
 
-use async_fetcher::{ChecksumSystem, FetchEvent};
-use async_std::task;
-use futures::{channel::mpsc, executor, prelude::*};
+use async_fetcher::{progress::ProgressTracker, Error as FetchError, FetchEvent};
+use futures::{channel::mpsc, prelude::*};
 use serde::{Deserialize, Serialize};
 use std::{
-    collections::HashMap,
     io::{self, Write},
     path::Path,
     sync::Arc,
-    time::Instant,
+    time::Duration,
 };
 
 pub async fn run(
@@ -21,113 +19,70 @@ pub async fn run(
     // Handles all callback events from the fetcher
     let mut events_tx_ = events_tx.clone();
     let fetch_events = async move {
-        let mut state = HashMap::<Arc<Path>, (u64, u64, Instant)>::new();
+        let tracker = ProgressTracker::new(Duration::from_millis(250));
 
         while let Some((dest, event)) = erx.next().await {
-            let event = match event {
-                FetchEvent::Progress(written) => {
-                    if let Some(progress) = state.get_mut(&dest) {
-                        progress.0 += written as u64;
-                        let now = Instant::now();
-
-                        if now.duration_since(progress.2).as_millis() > 250 {
-                            progress.2 = now;
-                            Output(
-                                fomat!((dest.display())),
-                                OutputEvent::Progress(progress.0, progress.1),
-                            )
-                        } else {
-                            continue;
-                        }
-                    } else {
-                        continue;
-                    }
-                }
-
-                FetchEvent::AlreadyFetched => {
-                    Output(fomat!((dest.display())), OutputEvent::AlreadyFetched)
+            let mut outputs = Vec::new();
+
+            // The progress of every file being fetched is written with each update.
+            if tracker.track(&dest, &event).is_some() {
+                for (dest, file) in tracker.files() {
+                    let progress =
+                        OutputEvent::Progress(file.bytes, file.length.unwrap_or(0));
+                    outputs.push(Output(fomat!((dest.display())), progress));
                 }
+            }
 
-                FetchEvent::ContentLength(length) => {
-                    state
-                        .entry(dest.clone())
-                        .and_modify(|bar| bar.1 = length)
-                        .or_insert((0, length, Instant::now()));
-
-                    Output(fomat!((dest.display())), OutputEvent::Length(length))
-                }
+            let event = match event {
+                FetchEvent::AlreadyFetched => Some(OutputEvent::AlreadyFetched),
+                FetchEvent::ContentLength(length) => Some(OutputEvent::Length(length)),
+                FetchEvent::Fetching => Some(OutputEvent::Fetching),
+                FetchEvent::Fetched => Some(OutputEvent::Fetched),
+                // Failures are written as their results are received.
+                _ => None,
+            };
 
-                FetchEvent::Fetching => {
-                    state.insert(dest.clone(), (0, 0, Instant::now()));
-                    Output(fomat!((dest.display())), OutputEvent::Fetching)
-                }
+            if let Some(event) = event {
+                outputs.push(Output(fomat!((dest.display())), event));
+            }
 
-                FetchEvent::Fetched => {
-                    state.remove(&dest);
-                    Output(fomat!((dest.display())), OutputEvent::Fetched)
+            for output in outputs {
+                if events_tx_.send(output).await.is_err() {
+                    return;
                 }
-
-                _ => continue,
-            };
-
-            if events_tx_.send(event).await.is_err() {
-                break;
             }
         }
     };
 
     // Handles all results from the fetcher.
-    let mut events_tx_ = events_tx.clone();
-    let (fetch_tx, mut fetch_rx) = mpsc::channel::<(Arc<Path>, _)>(0);
+    let (fetch_tx, mut fetch_rx) = mpsc::channel::<(Arc<Path>, Result<_, FetchError>)>(0);
     let fetch_results = async move {
         while let Some((dest, result)) = fetch_rx.next().await {
             let event = match result {
-                Ok(false) => None,
-                Ok(true) => {
-                    Some(Output(fomat!((dest.display())), OutputEvent::Validating))
+                Ok((_, false)) => None,
+                Ok((_, true)) => {
+                    Some(Output(fomat!((dest.display())), OutputEvent::Validated))
                 }
-                Err(why) => {
+                Err(why) if matches!(why.inner(), FetchError::Checksum(_)) => {
                     epintln!((dest.display()) " failed to validate: " [why]);
 
+                    Some(Output(fomat!((dest.display())), OutputEvent::Invalid))
+                }
+                Err(why) => {
+                    epintln!((dest.display()) " failed to fetch: " [why]);
+
                     Some(Output(fomat!((dest.display())), OutputEvent::Failed))
                 }
             };
 
             if let Some(event) = event {
-                if events_tx_.send(event).await.is_err() {
+                if events_tx.send(event).await.is_err() {
                     break;
                 }
             }
         }
     };
 
-    // Handles all results from checksum operations.
-    let (sum_tx, sum_rx) = mpsc::channel::<(Arc<Path>, _)>(0);
-    let sum_results = async move {
-        let mut stream = ChecksumSystem::new()
-            .build(sum_rx)
-            // Distribute each checksum future across a thread pool.
-            .map(|future| task::spawn_blocking(|| executor::block_on(future)))
-            // Limiting up to 32 concurrent tasks at a time.
-            .buffer_unordered(32);
-
-        while let Some((dest, result)) = stream.next().await {
-            let event = match result {
-                Ok(()) => Output(fomat!((dest.display())), OutputEvent::Validated),
-
-                Err(why) => {
-                    epintln!((dest.display()) " failed to validate: " [why]);
-
-                    Output(fomat!((dest.display())), OutputEvent::Invalid)
-                }
-            };
-
-            if events_tx.send(event).await.is_err() {
-                break;
-            }
-        }
-    };
-
     // Centrally writes all events to standard out.
     let stdout_writer = async move {
         let output = io::stdout();
@@ -154,9 +109,8 @@ pub async fn run(
     let _ = join!(
         stdout_writer,
         fetch_results,
-        sum_results,
         fetch_events,
-        execute(etx, fetch_tx, sum_tx).boxed_local()
+        execute(etx, fetch_tx).boxed_local()
     );
 }
 
@@ -9,16 +9,14 @@ mod inputs;
 mod interactive;
 mod machine;
 
-use async_fetcher::checksum::Checksum;
-
 use async_fetcher::{Error as FetchError, *};
 use async_std::{fs::File, task};
 use futures::{channel::mpsc, prelude::*};
 use std::{
-    io,
+    env, io,
     num::NonZeroU16,
     os::unix::io::{AsRawFd, FromRawFd},
-    path::Path,
+    path::{Path, PathBuf},
     sync::Arc,
     time::Duration,
 };
@@ -38,31 +36,50 @@ fn main() {
 
 async fn execute(
     etx: mpsc::UnboundedSender<(Arc<Path>, FetchEvent)>,
-    result_sender: mpsc::Sender<(Arc<Path>, Result<bool, FetchError>)>,
-    checksum_sender: mpsc::Sender<(Arc<Path>, Checksum)>,
+    result_sender: mpsc::Sender<(Arc<Path>, Result<(FetchOutcome, bool), FetchError>)>,
 ) {
+    // A Metalink document may be given in place of sources on standard input.
+    if let Some(document) = env::args_os().nth(1) {
+        let dir = env::args_os().nth(2).map_or_else(|| PathBuf::from("."), PathBuf::from);
+
+        match inputs::metalink(Path::new(&document), &dir).await {
+            Ok(input_stream) => fetcher_stream(etx, result_sender, input_stream).await,
+            Err(why) => {
+                epintln!("failed to read " (Path::new(&document).display()) ": " [why])
+            }
+        }
+
+        return;
+    }
+
     let stdin = io::stdin();
     let stdin = stdin.lock();
 
     let input_stream = inputs::stream(unsafe { File::from_raw_fd(stdin.as_raw_fd()) });
 
-    fetcher_stream(etx, result_sender, checksum_sender, input_stream).await
+    fetcher_stream(etx, result_sender, input_stream).await
 }
 
 /// The fetcher, which will be used to create futures for fetching files.
-async fn fetcher_stream<
-    S: Unpin + Send + Stream<Item = (Source, Option<Checksum>)> + 'static,
->(
+///
+/// Each source is paired with whether it has a checksum which is validated as it is
+/// fetched.
+async fn fetcher_stream<S: Unpin + Send + Stream<Item = (Source, bool)> + 'static>(
     event_sender: mpsc::UnboundedSender<(Arc<Path>, FetchEvent)>,
-    mut result_sender: mpsc::Sender<(Arc<Path>, Result<bool, FetchError>)>,
-    mut checksum_sender: mpsc::Sender<(Arc<Path>, Checksum)>,
+    mut result_sender: mpsc::Sender<(
+        Arc<Path>,
+        Result<(FetchOutcome, bool), FetchError>,
+    )>,
     sources: S,
 ) {
     let fetcher = Fetcher::new(Client::new())
         // Fetch each file in parts, using up to 4 concurrent connections per file
         .connections_per_file(NonZeroU16::new(4))
-        // Pass in the event sender which events will be sent to
-        .events(event_sender)
+        // Keep a record of each file, to revalidate and resume them by
+        .records(true)
+        // Pass in the event sender which events will be sent to, merging progress into
+        // periodic updates so that the output keeps up with fast connections
+        .events(events::Coalesce::new(event_sender, Duration::from_millis(100)))
         // Configure a timeout to bail when a connection stalls for too long
         .timeout(Duration::from_secs(15))
         // Wrap it in an Arc
@@ -74,18 +91,7 @@ async fn fetcher_stream<
         // Concurrently fetch up to 4 at a time
         .buffer_unordered(4);
 
-    while let Some((dest, result)) = fetcher.next().await {
-        match result {
-            Ok(Some(checksum)) => {
-                let _ = result_sender.send((dest.clone(), Ok(true)));
-                let _ = checksum_sender.send((dest, checksum)).await;
-            }
-            Ok(None) => {
-                let _ = result_sender.send((dest, Ok(false))).await;
-            }
-            Err(why) => {
-                let _ = result_sender.send((dest, Err(why))).await;
-            }
-        }
+    while let Some(result) = fetcher.next().await {
+        let _ = result_sender.send(result).await;
     }
 }
@@ -0,0 +1,176 @@
+//! A content-addressed store of fetched files, keyed by their checksums.
+//!
+//! Sources which share a checksum share a single entry in the store, so that identical
+//! content is only fetched once, regardless of the names that it is fetched under.
+
+use crate::checksum::Checksum;
+use async_std::fs;
+use filetime::FileTime;
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+/// How an entry is placed at a destination, or a destination is placed in the store.
+///
+/// When the chosen method is not supported by the filesystem, the file is copied.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Link {
+    /// Shares the entry's inode, which must then never be modified in place.
+    Hardlink,
+    /// Shares the entry's extents, copying them only when either file is modified.
+    #[default]
+    Reflink,
+    /// Writes a copy of the entry.
+    Copy,
+}
+
+/// A content-addressed store of files, evicting the least recently used when full.
+#[derive(Debug, new, Setters)]
+pub struct Cache {
+    /// The directory that entries are stored in.
+    #[setters(skip)]
+    root: PathBuf,
+
+    /// The number of bytes which entries may occupy before they are evicted.
+    #[new(default)]
+    #[setters(strip_option)]
+    limit: Option<u64>,
+
+    /// How entries are placed at their destinations.
+    #[new(default)]
+    link: Link,
+}
+
+impl Cache {
+    /// The location of the entry for content having the given checksum.
+    pub fn entry(&self, checksum: &Checksum) -> PathBuf {
+        let kind = match checksum {
+            Checksum::Md5(_) => "md5",
+            Checksum::Sha256(_) => "sha256",
+        };
+
+        self.root.join(kind).join(hex::encode(checksum.as_bytes()))
+    }
+
+    /// Places the entry for the checksum at `to`, if the store has one.
+    pub async fn get(&self, checksum: &Checksum, to: &Path) -> io::Result<bool> {
+        let entry = self.entry(checksum);
+        if !entry.exists() {
+            return Ok(false);
+        }
+
+        touch(&entry)?;
+        self.place(&entry, to).await?;
+        Ok(true)
+    }
+
+    /// Adds a file which has been verified against the checksum to the store.
+    pub async fn insert(&self, checksum: &Checksum, from: &Path) -> io::Result<()> {
+        let entry = self.entry(checksum);
+        if entry.exists() {
+            return touch(&entry);
+        }
+
+        let dir = entry.parent().expect("entries have a parent");
+        fs::create_dir_all(dir).await?;
+
+        // Entries are written beside their final location, so that they appear whole.
+        let mut temporary = entry.clone().into_os_string();
+        temporary.push(".tmp");
+        let temporary = PathBuf::from(temporary);
+
+        self.place(from, &temporary).await?;
+        fs::rename(&temporary, &entry).await?;
+        touch(&entry)?;
+
+        self.evict().await
+    }
+
+    /// Removes the least recently used entries until the store is within its limit.
+    pub async fn evict(&self) -> io::Result<()> {
+        let limit = match self.limit {
+            Some(limit) => limit,
+            None => return Ok(()),
+        };
+
+        let mut entries = Vec::new();
+        let mut total = 0;
+
+        for kind in &["md5", "sha256"] {
+            let dir = match std::fs::read_dir(self.root.join(kind)) {
+                Ok(dir) => dir,
+                Err(why) if why.kind() == io::ErrorKind::NotFound => continue,
+                Err(why) => return Err(why),
+            };
+
+            for entry in dir {
+                let entry = entry?;
+                let metadata = entry.metadata()?;
+                total += metadata.len();
+                entries.push((metadata.accessed()?, metadata.len(), entry.path()));
+            }
+        }
+
+        entries.sort_unstable_by_key(|entry| entry.0);
+
+        for (_, length, path) in entries {
+            if total <= limit {
+                break;
+            }
+
+            fs::remove_file(&path).await?;
+            total -= length;
+        }
+
+        Ok(())
+    }
+
+    async fn place(&self, from: &Path, to: &Path) -> io::Result<()> {
+        if to.exists() {
+            fs::remove_file(to).await?;
+        }
+
+        let linked = match self.link {
+            Link::Hardlink => fs::hard_link(from, to).await.is_ok(),
+            Link::Reflink => reflink(from, to).is_ok(),
+            Link::Copy => false,
+        };
+
+        if !linked {
+            fs::copy(from, to).await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Marks an entry as having been used, for the purpose of eviction.
+fn touch(entry: &Path) -> io::Result<()> {
+    filetime::set_file_atime(entry, FileTime::now())
+}
+
+/// Clones the extents of a file, on filesystems which support copy-on-write.
+#[cfg(target_os = "linux")]
+fn reflink(from: &Path, to: &Path) -> io::Result<()> {
+    use std::{fs::File, os::unix::io::AsRawFd};
+
+    const FICLONE: libc::c_ulong = 0x4004_9409;
+
+    let source = File::open(from)?;
+    let dest = File::create(to)?;
+
+    if unsafe { libc::ioctl(dest.as_raw_fd(), FICLONE as _, source.as_raw_fd()) } == -1 {
+        let why = io::Error::last_os_error();
+        drop(dest);
+        let _ = std::fs::remove_file(to);
+        return Err(why);
+    }
+
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn reflink(_from: &Path, _to: &Path) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Other, "reflinks are not supported"))
+}
@@ -2,11 +2,11 @@ use digest::{generic_array::GenericArray, Digest};
 use futures::prelude::*;
 use hex::FromHex;
 use md5::Md5;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use sha2::Sha256;
-use std::{convert::TryFrom, io};
+use std::{convert::TryFrom, io, num::NonZeroU64};
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum Checksum {
     Md5(GenericArray<u8, <Md5 as Digest>::OutputSize>),
     Sha256(GenericArray<u8, <Sha256 as Digest>::OutputSize>),
@@ -25,7 +25,7 @@ pub enum SumStr<'a> {
     Sha256(&'a str),
 }
 
-#[derive(Deserialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub enum SumStrBuf {
     Md5(String),
     Sha256(String),
@@ -40,6 +40,15 @@ impl SumStrBuf {
     }
 }
 
+impl From<&Checksum> for SumStrBuf {
+    fn from(checksum: &Checksum) -> Self {
+        match checksum {
+            Checksum::Md5(sum) => SumStrBuf::Md5(hex::encode(sum)),
+            Checksum::Sha256(sum) => SumStrBuf::Sha256(hex::encode(sum)),
+        }
+    }
+}
+
 impl<'a> TryFrom<SumStr<'a>> for Checksum {
     type Error = hex::FromHexError;
 
@@ -58,38 +67,84 @@ impl<'a> TryFrom<SumStr<'a>> for Checksum {
 impl Checksum {
     pub async fn validate<F: AsyncRead + Unpin>(
         &self,
-        reader: F,
+        mut reader: F,
         buffer: &mut [u8],
     ) -> Result<(), ChecksumError> {
+        let mut hasher = Hasher::new(self);
+        let mut read;
+
+        loop {
+            read = reader.read(buffer).await.map_err(ChecksumError::IO)?;
+
+            if read == 0 {
+                return hasher.verify(self);
+            }
+
+            hasher.input(&buffer[..read]);
+        }
+    }
+
+    /// The raw bytes of the digest.
+    pub fn as_bytes(&self) -> &[u8] {
         match self {
-            Checksum::Md5(sum) => checksum::<Md5, F>(reader, buffer, sum).await,
-            Checksum::Sha256(sum) => checksum::<Sha256, F>(reader, buffer, sum).await,
+            Checksum::Md5(sum) => sum.as_slice(),
+            Checksum::Sha256(sum) => sum.as_slice(),
         }
     }
 }
 
-async fn checksum<D: Digest, F: AsyncRead + Unpin>(
-    mut reader: F,
-    buffer: &mut [u8],
-    expected: &GenericArray<u8, D::OutputSize>,
-) -> Result<(), ChecksumError> {
-    let mut hasher = D::new();
-    let mut read;
-
-    loop {
-        read = reader.read(buffer).await.map_err(ChecksumError::IO)?;
-
-        if read == 0 {
-            let result = hasher.result();
-            return if result == *expected {
-                Ok(())
-            } else {
-                let expected = expected.clone().into_iter().collect::<Vec<u8>>().into();
-                let actual = result.into_iter().collect::<Vec<u8>>().into();
-                Err(ChecksumError::Invalid(expected, actual))
-            };
+/// Computes a checksum incrementally, as the bytes which it covers are fed to it.
+pub enum Hasher {
+    Md5(Md5),
+    Sha256(Sha256),
+}
+
+impl Hasher {
+    /// Creates a hasher of the same algorithm as the checksum.
+    pub fn new(checksum: &Checksum) -> Self {
+        match checksum {
+            Checksum::Md5(_) => Hasher::Md5(Md5::new()),
+            Checksum::Sha256(_) => Hasher::Sha256(Sha256::new()),
         }
+    }
 
-        hasher.input(&buffer[..read]);
+    pub fn input(&mut self, bytes: &[u8]) {
+        match self {
+            Hasher::Md5(hasher) => hasher.input(bytes),
+            Hasher::Sha256(hasher) => hasher.input(bytes),
+        }
     }
+
+    /// Compares the digest of every byte given so far with the expected checksum.
+    pub fn verify(self, expected: &Checksum) -> Result<(), ChecksumError> {
+        let actual: Box<[u8]> = match self {
+            Hasher::Md5(hasher) => hasher.result().as_slice().into(),
+            Hasher::Sha256(hasher) => hasher.result().as_slice().into(),
+        };
+
+        if *actual == *expected.as_bytes() {
+            Ok(())
+        } else {
+            Err(ChecksumError::Invalid(expected.as_bytes().into(), actual))
+        }
+    }
+}
+
+/// Checksums of consecutive pieces of a file, which are verified independently.
+#[derive(Clone, Debug)]
+pub struct Pieces {
+    /// The length of every piece, with the exception of the last.
+    pub size: NonZeroU64,
+
+    /// The checksum of each piece, in order.
+    pub checksums: Box<[Checksum]>,
+}
+
+impl Pieces {
+    pub fn new(size: NonZeroU64, checksums: impl Into<Box<[Checksum]>>) -> Self {
+        Self { size, checksums: checksums.into() }
+    }
+
+    /// The number of pieces that a file of the given length consists of.
+    pub fn count(&self, length: u64) -> u64 { length.div_ceil(self.size.get()) }
 }
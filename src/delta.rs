@@ -0,0 +1,434 @@
+//! Delta fetching, as performed by zsync.
+//!
+//! A `.zsync` control file lists a weak rolling checksum and a strong MD4 checksum for
+//! each block of a file. Every block which can be found at any offset of an older copy
+//! of the file is reused, and only the ranges of the remaining blocks are fetched.
+
+use async_std::{fs::File, prelude::*};
+use chrono::{DateTime, Utc};
+use hex::FromHex;
+use md4::{Digest as _, Md4};
+use sha1::{Digest as _, Sha1};
+use std::{collections::HashMap, io, num::NonZeroU64, path::Path, str, sync::Arc};
+
+/// The amount of the seed which is read at a time.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Error)]
+pub enum DeltaError {
+    #[error("control file lacks a header")]
+    Headerless,
+    #[error("control file header is not UTF-8")]
+    Encoding(#[source] str::Utf8Error),
+    #[error("invalid {} header: {}", _0, _1)]
+    Invalid(&'static str, Box<str>),
+    #[error("control file lacks the {} header", _0)]
+    Missing(&'static str),
+    #[error("expected {} bytes of block checksums, but found {}", expected, actual)]
+    Truncated { expected: u64, actual: u64 },
+}
+
+/// Reconstructs a file from an older copy of it, fetching only the blocks that changed.
+#[derive(Clone, Debug, new, Setters)]
+pub struct Delta {
+    /// The control file which describes every block of the file.
+    #[setters(skip)]
+    pub control: Arc<ControlFile>,
+
+    /// An older copy of the file to reuse blocks from.
+    ///
+    /// By default, this is the destination.
+    #[new(default)]
+    #[setters(strip_option)]
+    #[setters(into)]
+    pub seed: Option<Arc<Path>>,
+}
+
+/// The contents of a `.zsync` control file.
+#[derive(Debug)]
+pub struct ControlFile {
+    /// The name of the file that the control file describes.
+    pub filename: Option<Box<str>>,
+
+    /// The time that the file was last modified.
+    pub mtime: Option<DateTime<Utc>>,
+
+    /// The size of each block, which the final block is padded to with zeros.
+    pub block_size: NonZeroU64,
+
+    /// The length of the file.
+    pub length: u64,
+
+    /// URLs of the file, which may be relative to the URL of the control file.
+    pub urls: Box<[Box<str>]>,
+
+    /// The SHA-1 of the whole file, which the reconstructed file is verified against.
+    pub sha1: Option<[u8; 20]>,
+
+    rsum_bytes:     usize,
+    checksum_bytes: usize,
+    blocks:         Box<[Block]>,
+}
+
+#[derive(Debug)]
+struct Block {
+    rsum:     u32,
+    checksum: [u8; 16],
+}
+
+/// Which parts of a file can be copied from the seed, and which must be fetched.
+pub(crate) struct Plan {
+    /// Offsets in the file, offsets in the seed, and lengths of each reused block.
+    pub reused: Vec<(u64, u64, u64)>,
+
+    /// Inclusive ranges of the file which must be fetched.
+    pub missing: Vec<(u64, u64)>,
+}
+
+impl ControlFile {
+    /// Parses a control file, as generated by `zsyncmake`.
+    pub fn parse(data: &[u8]) -> Result<Self, DeltaError> {
+        let end = data
+            .windows(2)
+            .position(|window| window == b"\n\n")
+            .ok_or(DeltaError::Headerless)?;
+
+        let header = str::from_utf8(&data[..end]).map_err(DeltaError::Encoding)?;
+
+        let mut filename = None;
+        let mut mtime = None;
+        let mut block_size = None;
+        let mut length = None;
+        let mut hash_lengths = None;
+        let mut urls = Vec::new();
+        let mut sha1 = None;
+
+        for line in header.lines() {
+            let pos = match line.find(':') {
+                Some(pos) => pos,
+                None => continue,
+            };
+
+            let value = line[pos + 1..].trim();
+            let invalid = |key| DeltaError::Invalid(key, value.into());
+
+            match &line[..pos] {
+                "Filename" => filename = Some(value.into()),
+                "MTime" => {
+                    let time = DateTime::parse_from_rfc2822(value)
+                        .map_err(|_| invalid("MTime"))?;
+                    mtime = Some(time.with_timezone(&Utc));
+                }
+                "Blocksize" => {
+                    let size = value.parse::<u64>().ok().and_then(NonZeroU64::new);
+                    block_size = Some(size.ok_or_else(|| invalid("Blocksize"))?);
+                }
+                "Length" => {
+                    length = Some(value.parse::<u64>().map_err(|_| invalid("Length"))?);
+                }
+                "Hash-Lengths" => {
+                    let lengths = value
+                        .split(',')
+                        .map(|length| length.trim().parse::<usize>())
+                        .collect::<Result<Vec<usize>, _>>()
+                        .map_err(|_| invalid("Hash-Lengths"))?;
+
+                    match lengths.as_slice() {
+                        &[_, rsum @ 1..=4, checksum @ 3..=16] => {
+                            hash_lengths = Some((rsum, checksum));
+                        }
+                        _ => return Err(invalid("Hash-Lengths")),
+                    }
+                }
+                "URL" => urls.push(value.into()),
+                "SHA-1" => {
+                    sha1 =
+                        Some(<[u8; 20]>::from_hex(value).map_err(|_| invalid("SHA-1"))?);
+                }
+                _ => (),
+            }
+        }
+
+        let block_size = block_size.ok_or(DeltaError::Missing("Blocksize"))?;
+        let length = length.ok_or(DeltaError::Missing("Length"))?;
+        let (rsum_bytes, checksum_bytes) =
+            hash_lengths.ok_or(DeltaError::Missing("Hash-Lengths"))?;
+
+        let count = length.div_ceil(block_size.get());
+        let entry = rsum_bytes + checksum_bytes;
+        let checksums = &data[end + 2..];
+
+        if (checksums.len() as u64) < count * entry as u64 {
+            return Err(DeltaError::Truncated {
+                expected: count * entry as u64,
+                actual:   checksums.len() as u64,
+            });
+        }
+
+        let blocks = checksums
+            .chunks_exact(entry)
+            .take(count as usize)
+            .map(|entry| {
+                let rsum = entry[..rsum_bytes]
+                    .iter()
+                    .fold(0u32, |rsum, &byte| (rsum << 8) | u32::from(byte));
+
+                let mut checksum = [0u8; 16];
+                checksum[..checksum_bytes].copy_from_slice(&entry[rsum_bytes..]);
+
+                Block { rsum, checksum }
+            })
+            .collect();
+
+        Ok(Self {
+            filename,
+            mtime,
+            block_size,
+            length,
+            urls: urls.into(),
+            sha1,
+            rsum_bytes,
+            checksum_bytes,
+            blocks,
+        })
+    }
+
+    /// The length of a block, which is shorter than the block size for the final block.
+    fn block_length(&self, block: usize) -> u64 {
+        let start = block as u64 * self.block_size.get();
+        self.block_size.get().min(self.length - start)
+    }
+
+    /// The weak checksum of a window, truncated to the bytes stored in the control file.
+    fn rsum_key(&self, (a, b): (u16, u16)) -> u32 {
+        let rsum = (u32::from(a) << 16) | u32::from(b);
+        match self.rsum_bytes {
+            4 => rsum,
+            bytes => rsum & ((1 << (bytes * 8)) - 1),
+        }
+    }
+
+    /// Checks whether the window's strong checksum matches that of the block.
+    fn strong_match(&self, digest: &[u8], block: &Block) -> bool {
+        digest[..self.checksum_bytes] == block.checksum[..self.checksum_bytes]
+    }
+}
+
+/// Searches the seed for every block of the file at every offset.
+///
+/// Bytes beyond the end of the seed are treated as zeros, in the same way that the
+/// final block of the file is padded. Matching is done one block at a time, and the
+/// result must therefore be verified against the SHA-1 of the whole file.
+pub(crate) async fn plan(control: &ControlFile, seed: &Path) -> io::Result<Plan> {
+    let block_size = control.block_size.get() as usize;
+
+    let mut index = HashMap::<u32, Vec<usize>>::new();
+    for (id, block) in control.blocks.iter().enumerate() {
+        index.entry(block.rsum).or_default().push(id);
+    }
+
+    let mut found = vec![None; control.blocks.len()];
+    let mut remaining = found.len();
+
+    let mut file = File::open(seed).await?;
+    let seed_length = file.metadata().await?.len();
+
+    let mut buffer = Vec::with_capacity(CHUNK_SIZE + block_size);
+    let chunk = &mut vec![0u8; CHUNK_SIZE.max(block_size)];
+    let window = &mut vec![0u8; block_size];
+
+    let mut start = 0;
+    let mut offset = 0u64;
+    let mut eof = false;
+    let mut rsum = None;
+
+    while remaining != 0 && offset < seed_length {
+        // The window and the byte which follows it are kept in the buffer.
+        while !eof && buffer.len() - start <= block_size {
+            buffer.drain(..start);
+            start = 0;
+
+            let read = file.read(chunk).await?;
+            if read == 0 {
+                eof = true;
+            }
+
+            buffer.extend_from_slice(&chunk[..read]);
+        }
+
+        let byte = |pos: usize| buffer.get(pos).cloned().unwrap_or(0);
+
+        let (a, b) = match rsum {
+            Some(rsum) => rsum,
+            None => {
+                let end = buffer.len().min(start + block_size);
+                rsum_of(&buffer[start..end], block_size)
+            }
+        };
+
+        let mut matched = false;
+
+        if let Some(candidates) = index.get(&control.rsum_key((a, b))) {
+            let end = buffer.len().min(start + block_size);
+            window[..end - start].copy_from_slice(&buffer[start..end]);
+            window[end - start..].iter_mut().for_each(|byte| *byte = 0);
+
+            let digest = Md4::digest(&window[..]);
+
+            for &id in candidates {
+                let block = &control.blocks[id];
+                if found[id].is_none()
+                    && offset + control.block_length(id) <= seed_length
+                    && control.strong_match(&digest, block)
+                {
+                    found[id] = Some(offset);
+                    remaining -= 1;
+                    matched = true;
+                }
+            }
+        }
+
+        if matched {
+            // Blocks rarely overlap, so the search resumes after the matching block.
+            let skip = block_size.min(buffer.len() - start);
+            start += skip;
+            offset += skip as u64;
+            rsum = None;
+        } else {
+            let (out, into) = (byte(start), byte(start + block_size));
+            let a = a.wrapping_sub(u16::from(out)).wrapping_add(u16::from(into));
+            let b = b
+                .wrapping_sub((block_size as u16).wrapping_mul(u16::from(out)))
+                .wrapping_add(a);
+
+            start += 1;
+            offset += 1;
+            rsum = Some((a, b));
+        }
+    }
+
+    let mut plan = Plan { reused: Vec::new(), missing: Vec::new() };
+
+    for (id, found) in found.into_iter().enumerate() {
+        let target = id as u64 * control.block_size.get();
+        let length = control.block_length(id);
+
+        match (found, plan.missing.last_mut()) {
+            (Some(from), _) => plan.reused.push((target, from, length)),
+            // Adjacent blocks are fetched with a single range.
+            (None, Some(range)) if range.1 + 1 == target => range.1 = target + length - 1,
+            (None, _) => plan.missing.push((target, target + length - 1)),
+        }
+    }
+
+    Ok(plan)
+}
+
+/// Checks if the file matches the SHA-1 given by the control file.
+pub(crate) async fn verify(control: &ControlFile, path: &Path) -> io::Result<bool> {
+    let expected = match control.sha1 {
+        Some(expected) => expected,
+        None => return Ok(true),
+    };
+
+    let mut file = File::open(path).await?;
+    let buffer = &mut [0u8; 8 * 1024];
+    let mut hasher = Sha1::new();
+
+    loop {
+        let read = file.read(buffer).await?;
+        if read == 0 {
+            break;
+        }
+
+        hasher.input(&buffer[..read]);
+    }
+
+    Ok(hasher.result().as_slice() == expected)
+}
+
+/// The weak rolling checksum of zsync, over a block padded with zeros to its size.
+fn rsum_of(data: &[u8], block_size: usize) -> (u16, u16) {
+    let mut a = 0u16;
+    let mut b = 0u16;
+
+    for (pos, &byte) in data.iter().enumerate() {
+        a = a.wrapping_add(u16::from(byte));
+        b = b.wrapping_add(((block_size - pos) as u16).wrapping_mul(u16::from(byte)));
+    }
+
+    (a, b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A file of 20000 bytes, and its control file in the format written by zsyncmake
+    /// 0.6.2, which stores two bytes of each rolling sum and three of each MD4.
+    const FILE: &[u8] = include_bytes!("../tests/fixtures/delta.bin");
+    const CONTROL: &[u8] = include_bytes!("../tests/fixtures/delta.bin.zsync");
+
+    #[test]
+    fn parses_a_control_file() {
+        let control = ControlFile::parse(CONTROL).unwrap();
+
+        assert_eq!(control.filename.as_deref(), Some("delta.bin"));
+        assert_eq!(control.mtime.map(|mtime| mtime.timestamp()), Some(1_598_954_400));
+        assert_eq!(control.block_size.get(), 2048);
+        assert_eq!(control.length, FILE.len() as u64);
+        assert_eq!(&*control.urls, &[Box::from("delta.bin")]);
+        assert_eq!((control.rsum_bytes, control.checksum_bytes), (2, 3));
+        assert_eq!(control.blocks.len(), 10);
+        assert_eq!(control.block_length(9), 1568);
+
+        let sha1 = Sha1::digest(FILE);
+        assert_eq!(control.sha1.as_ref().map(|sha1| &sha1[..]), Some(sha1.as_slice()));
+
+        let truncated = &CONTROL[..CONTROL.len() - 1];
+        assert!(matches!(
+            ControlFile::parse(truncated),
+            Err(DeltaError::Truncated { .. })
+        ));
+    }
+
+    #[test]
+    fn plans_reuse_of_shifted_blocks() {
+        let control = ControlFile::parse(CONTROL).unwrap();
+
+        // Blocks are moved to unaligned offsets, and the third block is replaced.
+        let mut seed = b"shifted".to_vec();
+        seed.extend_from_slice(&FILE[..4096]);
+        seed.extend_from_slice(&[0xAA; 2048]);
+        seed.extend_from_slice(&FILE[6144..]);
+
+        let path = std::env::temp_dir()
+            .join(format!("async-fetcher-delta-seed-{}", std::process::id()));
+        std::fs::write(&path, &seed).unwrap();
+        let plan = async_std::task::block_on(plan(&control, &path));
+        std::fs::remove_file(&path).unwrap();
+        let plan = plan.unwrap();
+
+        assert_eq!(plan.missing, [(4096, 6143)]);
+        assert_eq!(plan.reused.len(), 9);
+
+        // The final block is found by padding the end of the seed with zeros.
+        assert_eq!(plan.reused.last(), Some(&(18432, 18439, 1568)));
+
+        // The file is reconstructed from the reused blocks and the missing ranges.
+        let mut file = vec![0u8; FILE.len()];
+        for &(target, from, length) in &plan.reused {
+            let (target, from, length) =
+                (target as usize, from as usize, length as usize);
+            assert_eq!(target + 7, from);
+            file[target..target + length].copy_from_slice(&seed[from..from + length]);
+        }
+
+        for &(start, end) in &plan.missing {
+            let (start, end) = (start as usize, end as usize);
+            file[start..=end].copy_from_slice(&FILE[start..=end]);
+        }
+
+        assert!(file == FILE);
+    }
+}
@@ -0,0 +1,157 @@
+//! Destinations for the events which the fetcher submits.
+//!
+//! Progress is reported for every read, which a slow consumer may be unable to keep up
+//! with. A bounded channel makes the fetcher wait for the consumer instead, whereas
+//! [`Coalesce`] merges progress into periodic updates.
+
+use crate::{BatchEvent, FetchEvent};
+use futures::{
+    channel::mpsc,
+    future::{self, FutureExt, LocalBoxFuture},
+    sink::SinkExt,
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    mem,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Receives the events which the fetcher submits.
+pub trait EventSink: Send + Sync {
+    /// Submits an event, which the fetcher waits on before it continues.
+    fn send(&self, event: (Arc<Path>, FetchEvent)) -> LocalBoxFuture<'_, ()>;
+
+    /// Submits an event of the whole batch, which belongs to no file.
+    ///
+    /// Sinks which carry only the events of files discard it by default.
+    fn send_batch(&self, _event: BatchEvent) -> LocalBoxFuture<'_, ()> {
+        future::ready(()).boxed_local()
+    }
+}
+
+/// Queues events without limit.
+impl EventSink for mpsc::UnboundedSender<(Arc<Path>, FetchEvent)> {
+    fn send(&self, event: (Arc<Path>, FetchEvent)) -> LocalBoxFuture<'_, ()> {
+        let _ = self.unbounded_send(event);
+        future::ready(()).boxed_local()
+    }
+}
+
+/// Waits for room in the channel, so that the fetcher never outpaces the receiver.
+impl EventSink for mpsc::Sender<(Arc<Path>, FetchEvent)> {
+    fn send(&self, event: (Arc<Path>, FetchEvent)) -> LocalBoxFuture<'_, ()> {
+        let mut sender = self.clone();
+        async move {
+            let _ = SinkExt::send(&mut sender, event).await;
+        }
+        .boxed_local()
+    }
+}
+
+/// Passes each event to a function, which is called by the fetcher and must not block.
+pub struct Callback<F>(pub F);
+
+impl<F: Fn(Arc<Path>, FetchEvent) + Send + Sync> EventSink for Callback<F> {
+    fn send(&self, (dest, event): (Arc<Path>, FetchEvent)) -> LocalBoxFuture<'_, ()> {
+        (self.0)(dest, event);
+        future::ready(()).boxed_local()
+    }
+}
+
+/// Merges progress into periodic updates before passing events on to another sink.
+///
+/// The progress of a file, and of each of its parts, is held until the interval has
+/// passed since its last update, or until another event of that file is submitted. No
+/// progress is lost, and the events of each file stay in order.
+pub struct Coalesce<S> {
+    sink:     S,
+    interval: Duration,
+    pending:  Mutex<HashMap<Arc<Path>, Held>>,
+}
+
+/// Progress of a file which has yet to be passed on.
+struct Held {
+    since:    Instant,
+    progress: usize,
+    parts:    BTreeMap<u64, ((u64, u64), usize)>,
+}
+
+impl Held {
+    fn new(since: Instant) -> Self { Self { since, progress: 0, parts: BTreeMap::new() } }
+
+    /// Events which report the progress that was held.
+    fn into_events(self) -> Vec<FetchEvent> {
+        let parts = self.parts.into_iter().map(|(part, (range, bytes))| {
+            FetchEvent::PartProgress { part, range, bytes }
+        });
+
+        Some(self.progress)
+            .filter(|&read| read != 0)
+            .map(FetchEvent::Progress)
+            .into_iter()
+            .chain(parts)
+            .collect()
+    }
+}
+
+impl<S: EventSink> Coalesce<S> {
+    pub fn new(sink: S, interval: Duration) -> Self {
+        Self { sink, interval, pending: Mutex::default() }
+    }
+
+    fn send_all(
+        &self,
+        dest: Arc<Path>,
+        events: Vec<FetchEvent>,
+    ) -> LocalBoxFuture<'_, ()> {
+        async move {
+            for event in events {
+                self.sink.send((dest.clone(), event)).await;
+            }
+        }
+        .boxed_local()
+    }
+}
+
+impl<S: EventSink> EventSink for Coalesce<S> {
+    fn send(&self, (dest, event): (Arc<Path>, FetchEvent)) -> LocalBoxFuture<'_, ()> {
+        let now = Instant::now();
+        let mut pending = self.pending.lock().expect("coalesced progress is poisoned");
+
+        let held = match event {
+            FetchEvent::Progress(read) => {
+                let held = pending.entry(dest.clone()).or_insert_with(|| Held::new(now));
+                held.progress += read;
+                held
+            }
+            FetchEvent::PartProgress { part, range, bytes } => {
+                let held = pending.entry(dest.clone()).or_insert_with(|| Held::new(now));
+                held.parts.entry(part).or_insert((range, 0)).1 += bytes;
+                held
+            }
+            event => {
+                let mut events =
+                    pending.remove(&dest).map_or_else(Vec::new, Held::into_events);
+                drop(pending);
+
+                events.push(event);
+                return self.send_all(dest, events);
+            }
+        };
+
+        if now.duration_since(held.since) < self.interval {
+            return future::ready(()).boxed_local();
+        }
+
+        let events = mem::replace(held, Held::new(now)).into_events();
+        drop(pending);
+
+        self.send_all(dest, events)
+    }
+
+    fn send_batch(&self, event: BatchEvent) -> LocalBoxFuture<'_, ()> {
+        self.sink.send_batch(event)
+    }
+}
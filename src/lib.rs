@@ -9,26 +9,72 @@ extern crate log;
 #[macro_use]
 extern crate thiserror;
 
+/// Records fields of the current span, when built with the `tracing` feature.
+macro_rules! record {
+    ($($field:literal = $value:expr),+) => {
+        #[cfg(feature = "tracing")]
+        {
+            let span = tracing::Span::current();
+            $(span.record($field, &$value);)+
+        }
+    };
+}
+
+pub mod cache;
 pub mod checksum;
+pub mod delta;
+pub mod events;
+pub mod metalink;
+pub mod metrics;
+mod outcome;
+mod preflight;
+pub mod progress;
 mod range;
+pub mod record;
+mod response;
+pub mod space;
 mod systems;
+#[cfg(test)]
+mod tests;
+pub mod transport;
+
+pub use self::{
+    outcome::{FetchOutcome, FetchStatus},
+    preflight::{Preflight, PreflightReport, PreflightStatus},
+    response::ResponseHead,
+    systems::*,
+};
 
-pub use self::systems::*;
+use self::{
+    cache::Cache,
+    checksum::{Checksum, ChecksumError, Hasher, Pieces, SumStrBuf},
+    delta::Delta,
+    events::EventSink,
+    metrics::Metrics,
+    record::{Record, Validators},
+    space::SpaceRequirements,
+    transport::{DataTransport, FileTransport, ReadRequest, Transport},
+};
 
 use std::{
-    fmt::Debug,
+    cell::RefCell,
+    collections::HashMap,
+    ffi::OsString,
+    fmt::{self, Debug},
     io,
+    io::SeekFrom,
     num::{NonZeroU16, NonZeroU32, NonZeroU64},
     path::Path,
     sync::{
         atomic::{AtomicBool, Ordering},
-        Arc,
+        Arc, Mutex,
     },
-    time::{Duration, UNIX_EPOCH},
+    time::{Duration, Instant, UNIX_EPOCH},
 };
 
 use async_std::{
-    fs::{self, File},
+    fs::{self, File, OpenOptions},
+    io::Read,
     prelude::*,
 };
 
@@ -36,33 +82,60 @@ use chrono::{DateTime, Utc};
 use filetime::FileTime;
 use futures::{
     channel::mpsc,
+    future,
     stream::{self, StreamExt},
 };
 use http::StatusCode;
 use http_client::native::NativeClient;
 use numtoa::NumToA;
-use surf::{
-    headers::Headers, middleware::HttpClient, Client, Exception, Request, Response,
-};
+use surf::{middleware::HttpClient, Client, Exception};
 
 pub type EventSender = mpsc::UnboundedSender<(Arc<Path>, FetchEvent)>;
 pub type Output<T> = (Arc<Path>, Result<T, Error>);
 
+/// A predicate which may reject a response before any of its body is written.
+///
+/// It is given the head of the whole resource: that of a response to a request for all
+/// of it, or of the probe which precedes a fetch in ranges. Responses to range requests
+/// only describe a part of the resource, and are not given to it.
+///
+/// The rejection message is returned to the caller in `Error::Rejected`.
+pub type Validator = Arc<dyn Fn(&ResponseHead) -> Result<(), Box<str>> + Send + Sync>;
+
 /// An error from the asynchronous file fetcher.
 #[derive(Debug, Error)]
 pub enum Error {
     #[error("task was cancelled")]
     Cancelled,
+    #[error("fetched file failed checksum validation")]
+    Checksum(#[source] ChecksumError),
     #[error("http client error")]
     Client(#[from] Exception),
     #[error("unable to concatenate fetched parts")]
     Concatenate(#[source] io::Error),
+    #[error("file reconstructed from a delta failed SHA-1 validation")]
+    DeltaChecksum,
+    #[error("{}", _0)]
+    Exhausted(Attempts),
     #[error("unable to create file")]
     FileCreate(#[source] io::Error),
     #[error("unable to set timestamp on {:?}", _0)]
     FileTime(Arc<Path>, #[source] io::Error),
+    #[error(
+        "requested bytes {}-{}, but the server responded with {:?}",
+        requested.0,
+        requested.1,
+        received
+    )]
+    ContentRange { requested: (u64, u64), received: Option<Box<str>> },
+    #[error("insufficient disk space: {} bytes needed, {} available", needed, available)]
+    InsufficientSpace { needed: u64, available: u64 },
+    #[error("malformed data URL: {}", _0)]
+    InvalidData(Box<str>),
     #[error("content length is an invalid range")]
     InvalidRange(#[source] io::Error),
+    #[error("unable to read local source {:?}", _0)]
+    Local(Arc<Path>, #[source] io::Error),
     #[error("unable to remove file with bad metadata")]
     MetadataRemove(#[source] io::Error),
     #[error("destination has no file name")]
@@ -71,18 +144,225 @@ pub enum Error {
     OpenPart(Arc<Path>, #[source] io::Error),
     #[error("destination lacks parent")]
     Parentless,
+    #[error("part {} failed checksum validation", part)]
+    PieceChecksum { part: u64, source: ChecksumError },
+    #[error("expected {} piece checksums, but {} were given", expected, actual)]
+    PieceCount { expected: u64, actual: u64 },
+    #[error("unable to write record {:?}", _0)]
+    Record(Arc<Path>, #[source] io::Error),
+    #[error("response was rejected: {}", _0)]
+    Rejected(Box<str>),
+    #[error("{}", _0)]
+    Request(RequestContext, #[source] Box<Error>),
     #[error("connection timed out")]
     TimedOut,
     #[error("error writing to file")]
     Write(#[source] io::Error),
     #[error("failed to rename partial to destination")]
     Rename(#[source] io::Error),
+    #[error("unable to read seed {:?}", _0)]
+    Seed(Arc<Path>, #[source] io::Error),
+    #[error("unable to sync {:?} to disk", _0)]
+    Sync(Arc<Path>, #[source] io::Error),
+    #[error("unable to query available disk space")]
+    SpaceQuery(#[source] io::Error),
     #[error("server responded with an error: {}", _0)]
-    Status(StatusCode),
+    Status(StatusCode, Box<str>),
+    #[error("expected to receive {} bytes, but received {}", expected, received)]
+    UnexpectedLength { expected: u64, received: u64 },
+    #[error("no transport is available for the {} scheme", _0)]
+    UnsupportedScheme(Box<str>),
+}
+
+impl Error {
+    /// Checks if the error may not occur again when the request is retried.
+    ///
+    /// Checksum failures are transient, as the file may have been corrupted in transit.
+    /// A fetch whose every attempt failed is transient if any one of them was.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Error::Exhausted(attempts) => {
+                attempts.0.iter().any(|attempt| attempt.error.is_transient())
+            }
+            Error::Request(_, source) => source.is_transient(),
+            Error::Status(status, _) => matches!(
+                *status,
+                StatusCode::REQUEST_TIMEOUT
+                    | StatusCode::TOO_MANY_REQUESTS
+                    | StatusCode::INTERNAL_SERVER_ERROR
+                    | StatusCode::BAD_GATEWAY
+                    | StatusCode::SERVICE_UNAVAILABLE
+                    | StatusCode::GATEWAY_TIMEOUT
+            ),
+            Error::Checksum(_)
+            | Error::Client(_)
+            | Error::PieceChecksum { .. }
+            | Error::TimedOut
+            | Error::UnexpectedLength { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// The error, without the context of the request that it occurred in.
+    ///
+    /// For a fetch whose every attempt failed, this is the error of the final attempt.
+    pub fn inner(&self) -> &Error {
+        match self {
+            Error::Exhausted(attempts) => attempts.last().inner(),
+            Error::Request(_, source) => source.inner(),
+            other => other,
+        }
+    }
+
+    /// The context of the request that the error occurred in, if it is known.
+    pub fn context(&self) -> Option<&RequestContext> {
+        match self {
+            Error::Exhausted(attempts) => attempts.last().context(),
+            Error::Request(context, _) => Some(context),
+            _ => None,
+        }
+    }
+
+    /// Attributes the error to a request, unless the fetch was cancelled.
+    fn within(self, context: RequestContext) -> Self {
+        match self {
+            Error::Cancelled | Error::Request(..) => self,
+            other => Error::Request(context, Box::new(other)),
+        }
+    }
+
+    /// Attributes the error to an attempt to fetch a source from a URL.
+    ///
+    /// Errors which are already attributed to a request keep its URL and range.
+    fn attempt(self, url: &str, attempt: u16) -> Self {
+        match self {
+            Error::Request(mut context, source) => {
+                context.attempt = attempt;
+                Error::Request(context, source)
+            }
+            other => other.within(RequestContext::new(url).attempt(attempt)),
+        }
+    }
+}
+
+/// Identifies the request which an error occurred in.
+#[derive(Clone, Debug, Setters)]
+pub struct RequestContext {
+    /// The URL which was requested.
+    #[setters(skip)]
+    pub url: Box<str>,
+
+    /// The part of the file which was being fetched, if it was fetched in parts.
+    #[setters(strip_option)]
+    pub part: Option<u64>,
+
+    /// The inclusive range of bytes which was requested.
+    #[setters(strip_option)]
+    pub range: Option<(u64, u64)>,
+
+    /// The attempt to fetch the source, counting from 1.
+    pub attempt: u16,
+}
+
+impl RequestContext {
+    pub fn new(url: &str) -> Self {
+        Self { url: url.into(), part: None, range: None, attempt: 0 }
+    }
+}
+
+impl fmt::Display for RequestContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "request to {}", self.url)?;
+
+        if let Some(part) = self.part {
+            write!(f, " for part {}", part)?;
+        }
+
+        if let Some((start, end)) = self.range {
+            write!(f, " (bytes {}-{})", start, end)?;
+        }
+
+        if self.attempt != 0 {
+            write!(f, " on attempt {}", self.attempt)?;
+        }
+
+        f.write_str(" failed")
+    }
+}
+
+/// An attempt to fetch a source which failed.
+#[derive(Debug)]
+pub struct FailedAttempt {
+    /// The mirror which the attempt was made to.
+    pub url: Box<str>,
+
+    /// When the attempt began, relative to the start of the fetch.
+    pub started: Duration,
+
+    /// How long the attempt took to fail.
+    pub elapsed: Duration,
+
+    /// Why the attempt failed.
+    pub error: Arc<Error>,
+}
+
+impl fmt::Display for FailedAttempt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.error.context() {
+            Some(context) => {
+                write!(f, "{} after {:?}: {}", context, self.elapsed, self.error.inner())
+            }
+            None => {
+                write!(f, "{} failed after {:?}: {}", self.url, self.elapsed, self.error)
+            }
+        }
+    }
+}
+
+/// Every attempt which was made to fetch a source, each of which failed.
+#[derive(Debug)]
+pub struct Attempts(pub Vec<FailedAttempt>);
+
+impl Attempts {
+    /// The error of the final attempt.
+    pub fn last(&self) -> &Error {
+        &self.0.last().expect("at least one attempt is made").error
+    }
+}
+
+impl fmt::Display for Attempts {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0.len() {
+            1 => f.write_str("the only attempt failed")?,
+            attempts => write!(f, "all {} attempts failed", attempts)?,
+        }
+
+        for (id, attempt) in self.0.iter().enumerate() {
+            f.write_str(if id == 0 { ": " } else { "; " })?;
+            fmt::Display::fmt(attempt, f)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// How much care is taken to ensure that a fetched file survives a crash.
+///
+/// Files are always written to a temporary location and renamed over their
+/// destination once complete, so that the destination is never left truncated.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Durability {
+    /// The file is renamed into place without waiting for it to reach the disk.
+    #[default]
+    Atomic,
+    /// The file is synced to the disk before it is renamed into place.
+    Synced,
+    /// The file is synced before the rename, and its directory is synced after it.
+    Durable,
 }
 
 /// Information about a source being fetched.
-#[derive(Debug, Setters)]
+#[derive(Clone, Debug, Setters)]
 pub struct Source {
     /// URLs whereby the file can be found.
     #[setters(skip)]
@@ -92,23 +372,53 @@ pub struct Source {
     #[setters(skip)]
     pub dest: Arc<Path>,
 
-    /// Optional location to store the partial file
+    /// Optional location to store the partial file.
+    ///
+    /// By default, this is a hidden sibling of the destination.
     #[setters(strip_option)]
     #[setters(into)]
     pub part: Option<Arc<Path>>,
+
+    /// The checksum which the file is verified against as it is being fetched.
+    #[setters(strip_option)]
+    #[setters(into)]
+    pub checksum: Option<Arc<Checksum>>,
+
+    /// Checksums of each piece of the file, which are verified as each is fetched.
+    #[setters(strip_option)]
+    #[setters(into)]
+    pub pieces: Option<Arc<Pieces>>,
+
+    /// Reuses blocks of an older copy of the file, so that only changes are fetched.
+    #[setters(strip_option)]
+    #[setters(into)]
+    pub delta: Option<Arc<Delta>>,
+
+    /// Revalidates the file with the server, even while its record says it is fresh.
+    pub revalidate: bool,
 }
 
 impl Source {
     pub fn new(urls: impl Into<Arc<[Box<str>]>>, dest: impl Into<Arc<Path>>) -> Self {
-        Self { urls: urls.into(), dest: dest.into(), part: None }
+        Self {
+            urls:       urls.into(),
+            dest:       dest.into(),
+            part:       None,
+            checksum:   None,
+            pieces:     None,
+            delta:      None,
+            revalidate: false,
+        }
     }
 }
 
 /// Events which are submitted by the fetcher.
 #[derive(Debug)]
 pub enum FetchEvent {
-    /// Signals that this file was already fetched.
+    /// Signals that this file was already fetched, and has not been fetched again.
     AlreadyFetched,
+    /// Notifies that the fetch was cancelled.
+    Cancelled,
     /// States that we know the length of the file being fetched.
     ContentLength(u64),
     /// Notifies that the file has been fetched.
@@ -117,10 +427,32 @@ pub enum FetchEvent {
     Fetching,
     /// Reports the amount of bytes that have been read for a file.
     Progress(usize),
-    /// Reports that a part of a file is being fetched.
-    PartFetching(u64),
+    /// Reports that a part of a file, spanning an inclusive range of bytes, is being
+    /// fetched.
+    PartFetching { part: u64, range: (u64, u64) },
     /// Reports that a part has been fetched.
-    PartFetched(u64),
+    PartFetched { part: u64, range: (u64, u64) },
+    /// Reports the amount of bytes that have been read for a part of a file.
+    PartProgress { part: u64, range: (u64, u64), bytes: usize },
+    /// Reports that a part failed verification, and that the bytes which were read for
+    /// it will be fetched again.
+    PartRetrying { part: u64, range: (u64, u64), bytes: u64 },
+    /// Names the mirror which an attempt to fetch the file begins with.
+    MirrorSelected(Box<str>),
+    /// Reports that a partial file is being resumed from this offset.
+    Resumed(u64),
+    /// Reports that an attempt failed, and that this attempt will be made next.
+    Retrying { attempt: u16, error: Arc<Error> },
+    /// Reports that the final attempt failed, with its error.
+    Failed(Arc<Error>),
+}
+
+/// Events which concern a whole batch, rather than any one of its files.
+#[derive(Debug)]
+pub enum BatchEvent {
+    /// Reports the number of files and bytes that a batch will fetch, as found by a
+    /// preflight check.
+    Size { files: usize, bytes: u64 },
 }
 
 /// An asynchronous file fetcher for clients fetching files.
@@ -134,6 +466,11 @@ pub struct Fetcher<C: HttpClient> {
     #[setters(skip)]
     client: Client<C>,
 
+    /// Transports which serve URL schemes in place of the built-in transports.
+    #[new(default)]
+    #[setters(skip)]
+    transports: HashMap<Box<str>, Arc<dyn Transport>>,
+
     /// When set, cancels any active operations.
     #[new(default)]
     #[setters(strip_option)]
@@ -151,16 +488,47 @@ pub struct Fetcher<C: HttpClient> {
     #[new(value = "unsafe { NonZeroU32::new_unchecked(2 * 1024 * 1024) }")]
     max_part_size: NonZeroU32,
 
+    /// How much care is taken to ensure that fetched files survive a crash.
+    #[new(default)]
+    durability: Durability,
+
     /// The time to wait between chunks before giving up.
     #[new(default)]
     #[setters(strip_option)]
     timeout: Option<Duration>,
 
-    /// Holds a sender for submitting events to.
+    /// Receives the events of each fetch.
+    #[new(default)]
+    #[setters(skip)]
+    events: Option<Arc<dyn EventSink>>,
+
+    /// Paths which events are reported under in place of those they are sent for, such
+    /// as the destinations of the files which a batch stages.
+    #[new(default)]
+    #[setters(skip)]
+    aliases: Mutex<HashMap<Arc<Path>, Arc<Path>>>,
+
+    /// A content-addressed store which sources with checksums are shared through.
+    #[new(default)]
+    #[setters(into)]
+    #[setters(strip_option)]
+    cache: Option<Arc<Cache>>,
+
+    /// Counters and histograms of the requests made, which are updated as they are made.
     #[new(default)]
     #[setters(into)]
     #[setters(strip_option)]
-    events: Option<Arc<EventSender>>,
+    metrics: Option<Arc<Metrics>>,
+
+    /// Keeps a record of each fetched file, which decides if it is already fetched, how
+    /// it is revalidated, and whether an interrupted fetch of it may be resumed.
+    #[new(default)]
+    records: bool,
+
+    /// Inspects the status and headers of each response before its body is written.
+    #[new(default)]
+    #[setters(strip_option)]
+    validator: Option<Validator>,
 }
 
 impl Default for Fetcher<NativeClient> {
@@ -171,153 +539,799 @@ impl<C: HttpClient> Fetcher<C> {
     /// Wraps the fetcher in an Arc.
     pub fn into_arc(self) -> Arc<Self> { Arc::new(self) }
 
+    /// Serves URLs of the given scheme with a custom transport.
+    ///
+    /// This takes precedence over the built-in transports, which serve `http`, `https`,
+    /// `file`, and `data` URLs.
+    pub fn with_transport(
+        mut self,
+        scheme: impl Into<Box<str>>,
+        transport: impl Transport + 'static,
+    ) -> Self {
+        self.transports.insert(scheme.into(), Arc::new(transport));
+        self
+    }
+
+    /// Submits the events of each fetch to a sink, such as a channel.
+    ///
+    /// The fetcher waits on the sink as each event is submitted, so a bounded channel
+    /// keeps a slow consumer from accumulating events without limit.
+    pub fn events(mut self, sink: impl EventSink + 'static) -> Self {
+        self.events = Some(Arc::new(sink));
+        self
+    }
+
     /// Request a file from one or more URIs.
     ///
     /// At least one URI must be provided as a source for the file. Each additional URI
-    /// serves as a mirror for failover and load-balancing purposes.
+    /// serves as a mirror for failover and load-balancing purposes: every retry begins
+    /// with the mirror following the one which failed before it.
     pub async fn request(
         self: Arc<Self>,
         uris: Arc<[Box<str>]>,
         to: Arc<Path>,
-    ) -> Result<(), Error> {
-        match self.clone().inner_request(uris.clone(), to.clone()).await {
-            Ok(()) => Ok(()),
-            Err(mut why) => {
-                for _ in 1..self.retries.get() {
-                    match self.clone().inner_request(uris.clone(), to.clone()).await {
-                        Ok(()) => return Ok(()),
-                        Err(cause) => why = cause,
-                    }
+    ) -> Result<FetchOutcome, Error> {
+        self.fetch(&Source::new(uris, to)).await
+    }
+
+    /// Fetch a source, replacing its destination only once the fetch is complete.
+    ///
+    /// The file is written to the source's partial location, and is renamed over the
+    /// destination with the configured `Durability`. If the source has a checksum, the
+    /// file is hashed as it is fetched, and is retried if the checksum does not match.
+    /// An existing destination which already matches the checksum is not fetched.
+    ///
+    /// Once verified, a file with a checksum is added to the cache, if one is set.
+    ///
+    /// If every attempt fails, each of their errors is returned in `Error::Exhausted`.
+    ///
+    /// `FetchEvent::Fetching` is sent as the fetch begins, and exactly one of `Fetched`,
+    /// `AlreadyFetched`, `Failed` or `Cancelled` is sent as it ends.
+    ///
+    /// With the `tracing` feature, the fetch is traced by a `fetch` span, which contains
+    /// an `attempt` span for each attempt, and a `request` span for each request made.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "fetch",
+            skip_all,
+            fields(
+                dest = ?source.dest,
+                bytes = tracing::field::Empty,
+                retries = tracing::field::Empty,
+                elapsed_ms = tracing::field::Empty,
+            )
+        )
+    )]
+    pub async fn fetch(self: Arc<Self>, source: &Source) -> Result<FetchOutcome, Error> {
+        let start = Instant::now();
+        let uris = source.urls.clone();
+        let dest = source.dest.clone();
+
+        self.send((dest.clone(), FetchEvent::Fetching)).await;
+
+        let mut failures = Vec::new();
+
+        // Each attempt begins with the next mirror.
+        let outcome = loop {
+            let attempt = failures.len() as u16;
+            let uris = rotate(&uris, attempt as usize);
+            let started = start.elapsed();
+
+            let mirror = FetchEvent::MirrorSelected(uris[0].clone());
+            self.send((dest.clone(), mirror)).await;
+
+            match self.clone().inner_request(uris.clone(), source).await {
+                Ok(outcome) => {
+                    record!(
+                        "bytes" = outcome.bytes,
+                        "retries" = attempt,
+                        "elapsed_ms" = start.elapsed().as_millis() as u64
+                    );
+
+                    break outcome.elapsed(start.elapsed()).retries(attempt);
+                }
+                Err(Error::Cancelled) => {
+                    self.send((dest, FetchEvent::Cancelled)).await;
+                    return Err(Error::Cancelled);
                 }
+                Err(why) => failures.push(FailedAttempt {
+                    url: uris[0].clone(),
+                    started,
+                    elapsed: start.elapsed() - started,
+                    error: Arc::new(why.attempt(&uris[0], attempt + 1)),
+                }),
+            }
+
+            let error = failures.last().expect("an attempt failed").error.clone();
+            let exhausted = failures.len() == self.retries.get() as usize;
+
+            if let Some(metrics) = self.metrics.as_ref() {
+                metrics.failed(&error, !exhausted);
+            }
 
-                Err(why)
+            if exhausted {
+                self.send((dest, FetchEvent::Failed(error))).await;
+                return Err(Error::Exhausted(Attempts(failures)));
+            }
+
+            let retrying = FetchEvent::Retrying { attempt: attempt + 2, error };
+            self.send((dest.clone(), retrying)).await;
+        };
+
+        if let (Some(cache), Some(checksum)) =
+            (self.cache.as_ref(), source.checksum.as_deref())
+        {
+            if let Err(why) = cache.insert(checksum, &dest).await {
+                error!("failed to add {:?} to the cache: {}", dest, why);
             }
         }
+
+        let event = match outcome.status {
+            FetchStatus::AlreadyFetched | FetchStatus::NotModified => {
+                FetchEvent::AlreadyFetched
+            }
+            FetchStatus::Downloaded | FetchStatus::Resumed => FetchEvent::Fetched,
+        };
+
+        self.send((dest, event)).await;
+
+        Ok(outcome)
     }
 
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "attempt",
+            skip_all,
+            fields(url = %uris[0])
+        )
+    )]
     async fn inner_request(
         self: Arc<Self>,
         uris: Arc<[Box<str>]>,
-        to: Arc<Path>,
-    ) -> Result<(), Error> {
+        source: &Source,
+    ) -> Result<FetchOutcome, Error> {
+        let to = source.dest.clone();
+        let part = match source.part.clone() {
+            Some(part) => part,
+            None => partial_path(&to)?,
+        };
+
+        let checksum = source.checksum.as_deref();
+        let transport = self.transport(&uris[0])?;
+
         let mut modified = None;
         let mut length = None;
         let mut if_modified_since = None;
+        let mut if_none_match = None;
+
+        // An existing file which matches its checksum is already fetched, and needs no
+        // request to confirm it. One which fails its checksum must be fetched in full.
+        let mut exists = to.exists();
+        if let (true, Some(checksum)) = (exists, checksum) {
+            if verify_existing(&to, checksum).await {
+                return Ok(FetchOutcome::new(FetchStatus::AlreadyFetched));
+            }
+
+            exists = false;
+        }
+
+        // Content which has been fetched before is taken from the cache.
+        if let (false, Some(cache), Some(checksum)) =
+            (exists, self.cache.as_ref(), checksum)
+        {
+            match cache.get(checksum, &part).await {
+                Ok(true) => {
+                    self.commit(&part, &to).await?;
+                    self.record(&to, &uris[0], &Validators::default(), Some(checksum))
+                        .await?;
+                    return Ok(FetchOutcome::new(FetchStatus::AlreadyFetched));
+                }
+                Ok(false) => (),
+                Err(why) => error!("failed to take {:?} from the cache: {}", to, why),
+            }
+        }
+
+        // A file's record takes precedence over its metadata, which is easily changed.
+        let mut record = match (exists, self.records) {
+            (true, true) => Record::load(&to).await,
+            _ => None,
+        };
+
+        let local_length = match record {
+            Some(_) => {
+                fs::metadata(to.as_ref()).await.ok().map(|metadata| metadata.len())
+            }
+            None => None,
+        };
+
+        // While the response it was fetched from is fresh, the file is not revalidated.
+        if let (Some(record), Some(local_length)) = (record.as_ref(), local_length) {
+            if !source.revalidate && record.is_fresh(local_length) {
+                return Ok(FetchOutcome::new(FetchStatus::AlreadyFetched)
+                    .validators(record.validators.clone()));
+            }
+        }
+
+        // The response to the first probe is reused by any which would follow it.
+        let mut probed = None;
 
         // If the file already exists, validate that it is the same.
-        if to.exists() {
-            if let Some(mut response) = head(&self.client, &*uris[0]).await? {
-                let headers = &(response.headers());
-                let content_length = content_length(headers);
-                modified = last_modified(headers);
-
-                if let (Some(content_length), Some(last_modified)) =
-                    (content_length, modified)
-                {
-                    match fs::metadata(to.as_ref()).await {
-                        Ok(metadata) => {
-                            let modified = metadata.modified().map_err(Error::Write)?;
-                            let ts = modified
-                                .duration_since(UNIX_EPOCH)
-                                .expect("time went backwards");
-
-                            if metadata.len() == content_length
-                                && ts.as_secs() == last_modified.timestamp() as u64
-                            {
-                                self.send((to, FetchEvent::AlreadyFetched));
-                                return Ok(());
+        if exists {
+            if let Some(head) = transport.probe(&uris[0]).await? {
+                modified = head.last_modified();
+                length = head.content_length();
+
+                match fs::metadata(to.as_ref()).await {
+                    Ok(metadata) => {
+                        if is_up_to_date(&head, &metadata, record.as_ref()) {
+                            if let Some(record) = record.as_mut() {
+                                record.fetched = Utc::now();
+                                record.validators.expires = head.expires();
+                                record.save(&to).await?;
                             }
 
-                            if_modified_since =
-                                Some(DateTime::<Utc>::from(modified).to_rfc2822());
-                            length = Some(content_length);
+                            return Ok(FetchOutcome::new(FetchStatus::AlreadyFetched)
+                                .urls(vec![uris[0].clone()])
+                                .validators(Validators::from_head(&head)));
                         }
-                        Err(why) => {
-                            error!("failed to fetch metadata of {:?}: {}", to, why);
-                            fs::remove_file(to.as_ref())
-                                .await
-                                .map_err(Error::MetadataRemove)?;
+
+                        // A file of another length than was expected is damaged, and is
+                        // fetched unconditionally so that it cannot be found unmodified.
+                        match record.as_ref() {
+                            Some(record) if record.length == Some(metadata.len()) => {
+                                if_modified_since = record.validators.last_modified;
+                                if_none_match = record.validators.etag.clone();
+                            }
+                            None if modified.is_some()
+                                && length == Some(metadata.len()) =>
+                            {
+                                if_modified_since =
+                                    metadata.modified().ok().map(DateTime::<Utc>::from);
+                            }
+                            _ => (),
                         }
                     }
+                    Err(why) => {
+                        error!("failed to fetch metadata of {:?}: {}", to, why);
+                        fs::remove_file(to.as_ref())
+                            .await
+                            .map_err(Error::MetadataRemove)?;
+                    }
+                }
+
+                probed = Some(head);
+            }
+        }
+
+        // Blocks of an older copy are reused, so that only the changes are fetched.
+        if let Some(delta) = source.delta.as_ref() {
+            let seed = delta.seed.clone().unwrap_or_else(|| to.clone());
+            let length = delta.control.length;
+
+            // Without a head of the whole resource to validate, it is fetched in full.
+            if self.validator.is_some() && probed.is_none() {
+                probed = transport.probe(&uris[0]).await?;
+            }
+
+            let validated = match (self.validator.as_ref(), probed.as_ref()) {
+                (Some(_), Some(head)) => {
+                    self.validate_head(head)?;
+                    true
+                }
+                (Some(_), None) => false,
+                (None, _) => true,
+            };
+
+            if validated
+                && seed.exists()
+                && transport.supports_range(&uris[0], length).await?
+            {
+                self.send((to.clone(), FetchEvent::ContentLength(length))).await;
+
+                match self
+                    .clone()
+                    .get_delta(uris.clone(), source, delta, seed, part.clone())
+                    .await
+                {
+                    Ok((bytes, urls)) => {
+                        let validators = Validators {
+                            last_modified: delta.control.mtime,
+                            ..Validators::default()
+                        };
+
+                        self.commit(&part, &to).await?;
+                        self.record(&to, &uris[0], &validators, checksum).await?;
+
+                        return Ok(FetchOutcome::new(FetchStatus::Downloaded)
+                            .bytes(bytes)
+                            .urls(urls)
+                            .validators(validators));
+                    }
+                    // Blocks which only appeared to match are discarded by a full fetch.
+                    Err(Error::DeltaChecksum) => {
+                        warn!("delta of {:?} failed validation; fetching in full", to);
+                    }
+                    Err(why) => return Err(why),
                 }
             }
         }
 
-        // If set, this will use multiple connections to download a file in parts.
-        if let Some(connections) = self.connections_per_file {
-            if let Some(mut response) = head(&self.client, &*uris[0]).await? {
-                let headers = &(response.headers());
-                modified = last_modified(headers);
+        if let Some(connections) = self.connections(source) {
+            let head = match probed.take() {
+                Some(head) => Some(head),
+                None => transport.probe(&uris[0]).await?,
+            };
+
+            if let Some(head) = head {
+                modified = head.last_modified();
                 let length = match length {
                     Some(length) => Some(length),
-                    None => content_length(headers),
+                    None => head.content_length(),
                 };
 
                 if let Some(length) = length {
-                    if supports_range(&self.client, &*uris[0], length).await? {
-                        self.send((to.clone(), FetchEvent::ContentLength(length)));
-
-                        return self
-                            .get_many(length, connections.get(), uris, to, modified)
-                            .await;
+                    if transport.supports_range(&uris[0], length).await? {
+                        self.validate_head(&head)?;
+                        self.send((to.clone(), FetchEvent::ContentLength(length))).await;
+                        let needed = self.required_space(source, length);
+                        self.ensure_space(&part, &to, needed, length)?;
+
+                        let (bytes, urls) = self
+                            .clone()
+                            .get_concatenated(
+                                length,
+                                connections.get(),
+                                uris.clone(),
+                                source,
+                                part.clone(),
+                                modified,
+                            )
+                            .await?;
+
+                        self.commit(&part, &to).await?;
+                        let validators = Validators::from_head(&head);
+                        self.record(&to, &uris[0], &validators, checksum).await?;
+
+                        return Ok(FetchOutcome::new(FetchStatus::Downloaded)
+                            .bytes(bytes)
+                            .urls(urls)
+                            .validators(validators));
                     }
                 }
             }
         }
 
-        let mut request = self.client.get(&*uris[0]).set_header("Expect", "");
-        if let Some(modified_since) = if_modified_since {
-            request = request.set_header("if-modified-since", modified_since.as_str());
-        }
+        let mut validators =
+            Validators { last_modified: modified, ..Validators::default() };
+        let mut hasher = checksum.map(Hasher::new);
 
-        let path =
-            match self.get(&mut modified, request, to.clone(), to.clone(), None).await {
-                Ok(path) => path,
-                // Server does not support if-modified-since
-                Err(Error::Status(StatusCode::NOT_IMPLEMENTED)) => {
-                    let request = self.client.get(&*uris[0]).set_header("Expect", "");
-                    self.get(&mut modified, request, to.clone(), to, None).await?
+        // A partial file left by an interrupted fetch of the same version is resumed.
+        let resumed = if self.records {
+            self.resume(&uris[0], &part, &to, hasher.as_mut(), &mut validators).await?
+        } else {
+            None
+        };
+
+        let result = if let Some(bytes) = resumed {
+            Ok((part.clone(), bytes))
+        } else {
+            let request = ReadRequest::new(&uris[0])
+                .if_modified_since(if_modified_since)
+                .if_none_match(if_none_match.as_deref());
+
+            match self
+                .get(
+                    &mut validators,
+                    request,
+                    part.clone(),
+                    to.clone(),
+                    None,
+                    hasher.as_mut(),
+                )
+                .await
+            {
+                // Server does not support conditional requests
+                Err(Error::Status(StatusCode::NOT_IMPLEMENTED, _)) => {
+                    let request = ReadRequest::new(&uris[0]);
+                    self.get(
+                        &mut validators,
+                        request,
+                        part.clone(),
+                        to.clone(),
+                        None,
+                        hasher.as_mut(),
+                    )
+                    .await
+                }
+                result => result,
+            }
+        };
+
+        let (path, bytes) = match result {
+            Ok(fetched) => fetched,
+            Err(why) => {
+                // What was written before the failure may be resumed by the next attempt.
+                if self.records && validators != Validators::default() && part.exists() {
+                    let record = Record {
+                        url: uris[0].clone(),
+                        validators,
+                        length: None,
+                        checksum: None,
+                        fetched: Utc::now(),
+                    };
+
+                    if let Err(why) = record.save(&part).await {
+                        error!("failed to record partial file {:?}: {}", part, why);
+                    }
                 }
-                Err(why) => return Err(why),
-            };
 
-        if let Some(modified) = modified {
+                return Err(why);
+            }
+        };
+
+        // The destination is already up to date.
+        if path == to {
+            if let Some(mut record) = record {
+                record.fetched = Utc::now();
+                record.validators.expires = validators.expires;
+                record.save(&to).await?;
+            }
+
+            return Ok(FetchOutcome::new(FetchStatus::NotModified)
+                .urls(vec![uris[0].clone()])
+                .validators(validators));
+        }
+
+        if let (Some(hasher), Some(checksum)) = (hasher, checksum) {
+            if let Err(why) = hasher.verify(checksum) {
+                // A corrupted partial file must not be resumed.
+                Record::remove(&part).await;
+                return Err(Error::Checksum(why));
+            }
+        }
+
+        if let Some(modified) = validators.last_modified {
             let filetime = FileTime::from_unix_time(modified.timestamp(), 0);
             filetime::set_file_times(&path, filetime, filetime)
                 .map_err(move |why| Error::FileTime(path, why))?;
         }
 
+        self.commit(&part, &to).await?;
+        Record::remove(&part).await;
+        self.record(&to, &uris[0], &validators, checksum).await?;
+
+        let status = match resumed {
+            Some(_) => FetchStatus::Resumed,
+            None => FetchStatus::Downloaded,
+        };
+
+        Ok(FetchOutcome::new(status)
+            .bytes(bytes)
+            .urls(vec![uris[0].clone()])
+            .validators(validators))
+    }
+
+    /// Completes a partial file left by an interrupted fetch of the same version.
+    ///
+    /// Returns the number of bytes which were fetched to complete it, or `None` if the
+    /// partial file cannot be resumed, and must be fetched anew.
+    async fn resume(
+        &self,
+        url: &str,
+        part: &Arc<Path>,
+        dest: &Arc<Path>,
+        mut hasher: Option<&mut Hasher>,
+        validators: &mut Validators,
+    ) -> Result<Option<u64>, Error> {
+        let record = match Record::load(part).await {
+            Some(record) if record.url.as_ref() == url => record,
+            _ => return Ok(None),
+        };
+
+        let offset = match fs::metadata(part.as_ref()).await {
+            Ok(metadata) => metadata.len(),
+            Err(_) => return Ok(None),
+        };
+
+        let transport = self.transport(url)?;
+        let head = match transport.probe(url).await? {
+            Some(head) => head,
+            None => return Ok(None),
+        };
+
+        let length = match head.content_length() {
+            Some(length) if offset != 0 && offset < length => length,
+            _ => return Ok(None),
+        };
+
+        // The offsets of ranges do not correspond to the bytes of a decoded body.
+        let encoded = matches!(
+            head.header("content-encoding"),
+            Some(encoding) if !encoding.eq_ignore_ascii_case("identity")
+        );
+
+        if encoded
+            || !record.validators.matches(&head)
+            || !transport.supports_range(url, length).await?
+        {
+            return Ok(None);
+        }
+
+        self.validate_head(&head)?;
+
+        *validators = record.validators;
+        self.send((dest.clone(), FetchEvent::ContentLength(length))).await;
+        self.send((dest.clone(), FetchEvent::Resumed(offset))).await;
+        self.send((dest.clone(), FetchEvent::Progress(offset as usize))).await;
+
+        let file = &mut OpenOptions::new()
+            .read(true)
+            .append(true)
+            .open(part.as_ref())
+            .await
+            .map_err(|why| Error::OpenPart(part.clone(), why))?;
+
+        // The bytes which were fetched before are hashed ahead of those which follow.
+        if let Some(hasher) = hasher.as_mut() {
+            let buffer = &mut [0u8; 8 * 1024];
+            loop {
+                let read = file.read(buffer).await.map_err(Error::Concatenate)?;
+                if read == 0 {
+                    break;
+                }
+
+                hasher.input(&buffer[..read]);
+            }
+        }
+
+        let mut remainder = part.as_os_str().to_owned();
+        remainder.push(".resume");
+        let remainder: Arc<Path> = Path::new(&remainder).into();
+
+        let request = ReadRequest::new(url).range((offset, length - 1));
+        let (path, bytes) = self
+            .get(&mut Validators::default(), request, remainder, dest.clone(), None, None)
+            .await?;
+
+        systems::concatenator(file, stream::once(future::ok(path)), hasher).await?;
+
+        Ok(Some(bytes))
+    }
+
+    /// Records the validators of a fetched file in its sidecar, if records are kept.
+    ///
+    /// Without any validators, the record is removed so that it cannot become stale.
+    async fn record(
+        &self,
+        to: &Path,
+        url: &str,
+        validators: &Validators,
+        checksum: Option<&Checksum>,
+    ) -> Result<(), Error> {
+        if !self.records {
+            return Ok(());
+        }
+
+        if *validators == Validators::default() {
+            Record::remove(to).await;
+            return Ok(());
+        }
+
+        let record = Record {
+            url:        url.into(),
+            validators: validators.clone(),
+            length:     fs::metadata(to).await.ok().map(|metadata| metadata.len()),
+            checksum:   checksum.map(SumStrBuf::from),
+            fetched:    Utc::now(),
+        };
+
+        record.save(to).await
+    }
+
+    /// Moves a completed partial file over its destination.
+    async fn commit(&self, part: &Arc<Path>, to: &Arc<Path>) -> Result<(), Error> {
+        if self.durability != Durability::Atomic {
+            sync(part).await?;
+        }
+
+        fs::rename(part.as_ref(), to.as_ref()).await.map_err(Error::Rename)?;
+
+        if self.durability == Durability::Durable {
+            sync(&Arc::from(space::directory_of(to))).await?;
+        }
+
         Ok(())
     }
 
+    /// The disk space needed to fetch a source of the given length.
+    ///
+    /// A file fetched in parts requires twice its length, as the parts coexist with the
+    /// file that they are being concatenated into.
+    pub fn required_space(&self, source: &Source, length: u64) -> u64 {
+        match self.connections(source) {
+            Some(_) => 2 * length,
+            None => length,
+        }
+    }
+
+    /// The number of connections to fetch a source in parts with, if it is to be.
+    ///
+    /// If set, this will use multiple connections to download a file in parts. Files
+    /// with piece checksums are always fetched in parts, so that pieces are verified.
+    fn connections(&self, source: &Source) -> Option<NonZeroU16> {
+        self.connections_per_file.or_else(|| {
+            source.pieces.as_ref().map(|_| unsafe { NonZeroU16::new_unchecked(1) })
+        })
+    }
+
+    /// Rejects a response, by the head of the whole resource, if a validator is set.
+    fn validate_head(&self, head: &ResponseHead) -> Result<(), Error> {
+        match self.validator.as_ref() {
+            Some(validator) => validator(head).map_err(Error::Rejected),
+            None => Ok(()),
+        }
+    }
+
+    /// Fails early if the filesystem holding the partial file lacks the space needed
+    /// to fetch it, or the filesystem of its destination lacks room for the file.
+    fn ensure_space(
+        &self,
+        part: &Path,
+        dest: &Path,
+        needed: u64,
+        length: u64,
+    ) -> Result<(), Error> {
+        let mut required = SpaceRequirements::default();
+        required.add(space::directory_of(part), needed)?;
+        required.at_least(space::directory_of(dest), length)?;
+        required.check()
+    }
+
+    /// Fetches the response to a request into `to`, and returns the location of the
+    /// up-to-date content with the number of bytes received. If the server reports that
+    /// `dest` has not been modified, `dest` is returned and nothing is written.
+    ///
+    /// Validators of the response which are not yet known are stored in `validators`.
+    /// If the request is for a part of the file, its progress is reported for that part.
+    #[allow(clippy::too_many_arguments)]
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "request",
+            skip_all,
+            fields(
+                url = %request.url,
+                range = ?request.range,
+                bytes = tracing::field::Empty,
+                elapsed_ms = tracing::field::Empty,
+            )
+        )
+    )]
     async fn get(
         &self,
-        modified: &mut Option<DateTime<Utc>>,
-        request: Request<C>,
+        validators: &mut Validators,
+        request: ReadRequest<'_>,
         to: Arc<Path>,
         dest: Arc<Path>,
-        length: Option<u64>,
-    ) -> Result<Arc<Path>, Error> {
+        part: Option<u64>,
+        hasher: Option<&mut Hasher>,
+    ) -> Result<(Arc<Path>, u64), Error> {
+        let started = Instant::now();
+        let _connection = self.metrics.as_ref().map(|metrics| metrics.connect());
+
+        let (url, range) = (request.url, request.range);
+        let reader = self.transport(url)?.read(request);
+
+        let response = &mut match self.timeout {
+            Some(duration) => timed(duration, reader).await??,
+            None => reader.await?,
+        };
+
+        if let Some(metrics) = self.metrics.as_ref() {
+            metrics.responded(url, started.elapsed());
+        }
+
+        let head = &response.head;
+
+        if validators.last_modified.is_none() {
+            validators.last_modified = head.last_modified();
+        }
+
+        if validators.etag.is_none() {
+            validators.etag = head.etag().map(Box::from);
+        }
+
+        if validators.expires.is_none() {
+            validators.expires = head.expires();
+        }
+
+        if head.status == StatusCode::NOT_MODIFIED {
+            return Ok((dest, 0));
+        }
+
+        if range.is_none() {
+            self.validate_head(head)?;
+        }
+
+        let expected = match range {
+            Some(requested) => {
+                let received = head.header("content-range");
+                if head.status != StatusCode::PARTIAL_CONTENT
+                    || received.and_then(range::parse_content_range) != Some(requested)
+                {
+                    return Err(Error::ContentRange {
+                        requested,
+                        received: received.map(Box::from),
+                    });
+                }
+
+                Some(requested.1 - requested.0 + 1)
+            }
+            None => {
+                let content_length = head.content_length();
+
+                // Parts have had their space accounted for before they were requested.
+                if let Some(content_length) = content_length {
+                    self.ensure_space(&to, &dest, content_length, content_length)?;
+                }
+
+                // The length of an encoded body differs from the length that is read.
+                let expected = match head.header("content-encoding") {
+                    Some(encoding) if !encoding.eq_ignore_ascii_case("identity") => None,
+                    _ => content_length,
+                };
+
+                if let Some(length) = expected {
+                    self.send((dest.clone(), FetchEvent::ContentLength(length))).await;
+                }
+
+                expected
+            }
+        };
+
         let mut file = File::create(to.as_ref()).await.map_err(Error::FileCreate)?;
 
-        if let Some(length) = length {
+        if let (Some(_), Some(length)) = (range, expected) {
             file.set_len(length).await.map_err(Error::Write)?;
         }
 
-        let response = &mut validate(if let Some(duration) = self.timeout {
-            timed(duration, async { request.await.map_err(Error::from) }).await??
-        } else {
-            request.await?
-        })?;
+        let part = part.zip(range);
+        let mut received = 0;
+        let result = self
+            .stream(&mut response.body, &mut file, &dest, part, hasher, &mut received)
+            .await;
 
-        if modified.is_none() {
-            *modified = last_modified(&(response.headers()));
+        // Bytes are counted even when the transfer fails partway through.
+        if let Some(metrics) = self.metrics.as_ref() {
+            metrics.received(url, received);
         }
 
-        if response.status() == StatusCode::NOT_MODIFIED {
-            return Ok(to);
+        record!("bytes" = received, "elapsed_ms" = started.elapsed().as_millis() as u64);
+        result?;
+
+        if let Some(expected) = expected {
+            if received != expected {
+                return Err(Error::UnexpectedLength { expected, received });
+            }
         }
 
+        Ok((to, received))
+    }
+
+    /// Writes everything from the reader into the file, reporting progress as it goes.
+    ///
+    /// The number of bytes that were written is added to `written` as they are written.
+    async fn stream<R: Read + Unpin>(
+        &self,
+        reader: &mut R,
+        file: &mut File,
+        dest: &Arc<Path>,
+        part: Option<(u64, (u64, u64))>,
+        mut hasher: Option<&mut Hasher>,
+        written: &mut u64,
+    ) -> Result<(), Error> {
         let buffer = &mut [0u8; 8 * 1024];
         let mut read;
 
@@ -326,7 +1340,7 @@ impl<C: HttpClient> Fetcher<C> {
                 return Err(Error::Cancelled);
             }
 
-            let reader = async { response.read(buffer).await.map_err(Error::Write) };
+            let reader = async { reader.read(buffer).await.map_err(Error::Write) };
 
             read = match self.timeout {
                 Some(duration) => timed(duration, reader).await??,
@@ -334,145 +1348,455 @@ impl<C: HttpClient> Fetcher<C> {
             };
 
             if read != 0 {
-                self.send((dest.clone(), FetchEvent::Progress(read)));
+                self.send((dest.clone(), FetchEvent::Progress(read))).await;
+
+                if let Some((part, range)) = part {
+                    let event = FetchEvent::PartProgress { part, range, bytes: read };
+                    self.send((dest.clone(), event)).await;
+                }
+
+                if let Some(hasher) = hasher.as_mut() {
+                    hasher.input(&buffer[..read]);
+                }
 
                 file.write_all(&buffer[..read]).await.map_err(Error::Write)?;
+                *written += read as u64;
             } else {
                 break;
             }
         }
 
-        Ok(to)
+        Ok(())
     }
 
-    async fn get_many(
+    /// Fetches a file in parts of at most `max_part_size`, or of its pieces, and
+    /// concatenates them into the partial file.
+    async fn get_concatenated(
         self: Arc<Self>,
         length: u64,
         concurrent: u16,
         uris: Arc<[Box<str>]>,
-        to: Arc<Path>,
-        mut modified: Option<DateTime<Utc>>,
-    ) -> Result<(), Error> {
-        let parent = to.parent().ok_or(Error::Parentless)?;
-        let filename = to.file_name().ok_or(Error::Nameless)?;
+        source: &Source,
+        part: Arc<Path>,
+        modified: Option<DateTime<Utc>>,
+    ) -> Result<(u64, Vec<Box<str>>), Error> {
+        let checksum = source.checksum.as_deref();
+
+        // Parts are aligned to pieces, so that each may be verified on its own.
+        let max_part_size = match source.pieces.as_ref() {
+            Some(pieces) => {
+                let expected = pieces.count(length);
+                if expected != pieces.checksums.len() as u64 {
+                    let actual = pieces.checksums.len() as u64;
+                    return Err(Error::PieceCount { expected, actual });
+                }
 
-        let mut buf = [0u8; 20];
+                pieces.size
+            }
+            None => unsafe {
+                NonZeroU64::new_unchecked(u64::from(self.max_part_size.get()))
+            },
+        };
 
-        // The destination which parts will be concatenated to.
+        // The partial file which parts will be concatenated to.
         let concatenated_file =
-            &mut File::create(to.as_ref()).await.map_err(Error::FileCreate)?;
+            &mut File::create(part.as_ref()).await.map_err(Error::FileCreate)?;
+
+        let ranges = range::generate(length, max_part_size).collect();
+        let mut hasher = checksum.map(Hasher::new);
+
+        let used = self
+            .get_many(
+                ranges,
+                concurrent,
+                uris,
+                source,
+                &part,
+                concatenated_file,
+                Assembly::Concatenate(hasher.as_mut()),
+            )
+            .await?;
+
+        if let (Some(hasher), Some(checksum)) = (hasher, checksum) {
+            hasher.verify(checksum).map_err(Error::Checksum)?;
+        }
 
-        let max_part_size =
-            unsafe { NonZeroU64::new_unchecked(u64::from(self.max_part_size.get())) };
+        if let Some(modified) = modified {
+            let filetime = FileTime::from_unix_time(modified.timestamp(), 0);
+            filetime::set_file_times(&part, filetime, filetime)
+                .map_err(|why| Error::FileTime(part.clone(), why))?;
+        }
+
+        Ok(used)
+    }
+
+    /// Fetches each range as a part, spread across the mirrors, and assembles them into
+    /// the partial file as they arrive.
+    ///
+    /// Returns the number of bytes received, and the URLs which they were received from.
+    #[allow(clippy::too_many_arguments)]
+    async fn get_many(
+        self: Arc<Self>,
+        ranges: Vec<(u64, u64)>,
+        concurrent: u16,
+        uris: Arc<[Box<str>]>,
+        source: &Source,
+        part: &Path,
+        file: &mut File,
+        assembly: Assembly<'_>,
+    ) -> Result<(u64, Vec<Box<str>>), Error> {
+        let to = source.dest.clone();
+        let parent = part.parent().ok_or(Error::Parentless)?;
+        let filename = part.file_name().ok_or(Error::Nameless)?;
+
+        // Pieces are only aligned to the parts of a file which is fetched in order.
+        let pieces = match assembly {
+            Assembly::Concatenate(_) => source.pieces.clone(),
+            Assembly::Splice => None,
+        };
+
+        let mut buf = [0u8; 20];
+
+        let used = &RefCell::new((0, Vec::new()));
 
         let to_ = to.clone();
-        let parts = stream::iter(range::generate(length, max_part_size).enumerate())
+        let parts = stream::iter(ranges.into_iter().enumerate())
             // Generate a future for fetching each part that a range describes.
             .map(move |(partn, (range_start, range_end))| {
-                let uri = uris[partn % uris.len()].clone();
+                let uris = uris.clone();
+                let pieces = pieces.clone();
 
-                let part_path = {
+                let part_path: Arc<Path> = {
                     let mut new_filename = filename.to_os_string();
-                    new_filename
-                        .push(&[".part", partn.numtoa_str(10, &mut buf)].concat());
-                    parent.join(new_filename)
+                    new_filename.push(".part");
+                    new_filename.push(partn.numtoa_str(10, &mut buf));
+                    parent.join(new_filename).into()
                 };
 
                 let fetcher = self.clone();
                 let to = to_.clone();
 
-                async move {
-                    let range = range::to_string(range_start, range_end);
-
-                    fetcher.send((to.clone(), FetchEvent::PartFetching(partn as u64)));
-
-                    let request = fetcher
-                        .client
-                        .get(&*uri)
-                        .set_header("range", range.as_str())
-                        .set_header("Expect", "");
-
-                    let result = fetcher
-                        .get(
-                            &mut modified,
-                            request,
-                            part_path.into(),
-                            to.clone(),
-                            Some(range_end - range_start),
-                        )
+                let future = async move {
+                    let (part, range) = (partn as u64, (range_start, range_end));
+                    fetcher
+                        .send((to.clone(), FetchEvent::PartFetching { part, range }))
                         .await;
 
-                    fetcher.send((to, FetchEvent::PartFetched(partn as u64)));
+                    let checksum = pieces.as_ref().map(|pieces| &pieces.checksums[partn]);
+                    let mut attempt = 0;
+
+                    let result = loop {
+                        // A corrupted piece is fetched again from the next mirror.
+                        let uri = &uris[(partn + attempt) % uris.len()];
+                        let request =
+                            ReadRequest::new(uri).range((range_start, range_end));
+                        let mut hasher = checksum.map(Hasher::new);
+
+                        let result = fetcher
+                            .get(
+                                &mut Validators::default(),
+                                request,
+                                part_path.clone(),
+                                to.clone(),
+                                Some(partn as u64),
+                                hasher.as_mut(),
+                            )
+                            .await;
+
+                        let context = || {
+                            RequestContext::new(uri)
+                                .part(partn as u64)
+                                .range((range_start, range_end))
+                        };
+
+                        let result = result
+                            .map(|(path, bytes)| (range_start, path, bytes, uri.clone()))
+                            .map_err(|why| why.within(context()));
+
+                        let (fetched, hasher, checksum) = match (result, hasher, checksum)
+                        {
+                            (Ok(fetched), Some(hasher), Some(checksum)) => {
+                                (fetched, hasher, checksum)
+                            }
+                            (result, ..) => break result,
+                        };
+
+                        match hasher.verify(checksum) {
+                            Ok(()) => break Ok(fetched),
+                            Err(source) => {
+                                attempt += 1;
+                                if attempt >= fetcher.retries.get() as usize {
+                                    let part = partn as u64;
+                                    let why = Error::PieceChecksum { part, source };
+                                    break Err(why.within(context()));
+                                }
+
+                                let bytes = fetched.2;
+                                let event = FetchEvent::PartRetrying { part, range, bytes };
+                                fetcher.send((to.clone(), event)).await;
+                            }
+                        }
+                    };
+
+                    if result.is_ok() {
+                        fetcher.send((to, FetchEvent::PartFetched { part, range })).await;
+                    }
 
                     result
-                }
+                };
+
+                // Requests for a part, including those which refetch a corrupted piece,
+                // are traced within a span of the part.
+                #[cfg(feature = "tracing")]
+                let future = tracing::Instrument::instrument(
+                    future,
+                    tracing::info_span!("part", part = partn, range = ?(range_start, range_end)),
+                );
+
+                future
             })
             // Ensure that only this many connections are happenning concurrently at a
             // time
             .buffered(concurrent as usize)
+            // Tally the bytes received, and the URLs which they were received from.
+            .map(|result| {
+                result.map(|(offset, path, bytes, uri)| {
+                    let (received, urls) = &mut *used.borrow_mut();
+                    *received += bytes;
+                    if !urls.contains(&uri) {
+                        urls.push(uri);
+                    }
+
+                    (offset, path)
+                })
+            })
             // This type exploded the stack, and therefore needs to be boxed
             .boxed_local();
 
-        systems::concatenator(concatenated_file, parts).await?;
+        match assembly {
+            Assembly::Concatenate(hasher) => {
+                let parts = parts.map(|result| result.map(|(_, path)| path));
+                systems::concatenator(file, parts, hasher).await?;
+            }
+            Assembly::Splice => {
+                let mut parts = parts;
+                while let Some(result) = parts.next().await {
+                    let (offset, path) = result?;
+                    splice(file, offset, path).await?;
+                }
+            }
+        }
 
-        if let Some(modified) = modified {
+        Ok(used.take())
+    }
+
+    /// Reconstructs a file from the blocks of its seed, fetching only the missing ranges.
+    async fn get_delta(
+        self: Arc<Self>,
+        uris: Arc<[Box<str>]>,
+        source: &Source,
+        delta: &Delta,
+        seed: Arc<Path>,
+        part: Arc<Path>,
+    ) -> Result<(u64, Vec<Box<str>>), Error> {
+        let to = source.dest.clone();
+        let control = &delta.control;
+        let seed_error = |why| Error::Seed(seed.clone(), why);
+
+        let plan = delta::plan(control, &seed).await.map_err(seed_error)?;
+
+        // The remaining ranges are fetched in parts, spread across each of the mirrors.
+        let max_part_size =
+            unsafe { NonZeroU64::new_unchecked(u64::from(self.max_part_size.get())) };
+
+        let ranges = plan
+            .missing
+            .iter()
+            .flat_map(|&(start, end)| {
+                range::generate(end - start + 1, max_part_size)
+                    .map(move |(from, to)| (start + from, start + to))
+            })
+            .collect::<Vec<(u64, u64)>>();
+
+        let concurrent = self.connections_per_file.map_or(1, NonZeroU16::get);
+
+        // Parts are spliced as they arrive, so only those in flight coexist with the
+        // file.
+        let missing = ranges.iter().map(|(start, end)| end - start + 1).sum::<u64>();
+        let in_flight = missing.min(u64::from(concurrent) * max_part_size.get());
+        self.ensure_space(&part, &to, control.length + in_flight, control.length)?;
+
+        let file = &mut File::create(part.as_ref()).await.map_err(Error::FileCreate)?;
+        file.set_len(control.length).await.map_err(Error::Write)?;
+
+        // Blocks which are unchanged are copied from the seed.
+        let mut seed_file = File::open(seed.as_ref()).await.map_err(seed_error)?;
+        let mut buffer = vec![0u8; control.block_size.get() as usize];
+
+        for &(target, from, length) in &plan.reused {
+            let buffer = &mut buffer[..length as usize];
+            seed_file.seek(SeekFrom::Start(from)).await.map_err(seed_error)?;
+            seed_file.read_exact(buffer).await.map_err(seed_error)?;
+            file.seek(SeekFrom::Start(target)).await.map_err(Error::Write)?;
+            file.write_all(buffer).await.map_err(Error::Write)?;
+
+            self.send((to.clone(), FetchEvent::Progress(length as usize))).await;
+        }
+
+        let used = self
+            .clone()
+            .get_many(ranges, concurrent, uris, source, &part, file, Assembly::Splice)
+            .await?;
+
+        file.flush().await.map_err(Error::Write)?;
+
+        if !delta::verify(control, &part).await.map_err(Error::Write)? {
+            return Err(Error::DeltaChecksum);
+        }
+
+        if let Some(checksum) = source.checksum.as_deref() {
+            let reader = File::open(part.as_ref())
+                .await
+                .map_err(|why| Error::OpenPart(part.clone(), why))?;
+            checksum
+                .validate(reader, &mut [0u8; 8 * 1024])
+                .await
+                .map_err(Error::Checksum)?;
+        }
+
+        if let Some(modified) = control.mtime {
             let filetime = FileTime::from_unix_time(modified.timestamp(), 0);
-            filetime::set_file_times(&to, filetime, filetime)
-                .map_err(|why| Error::FileTime(to, why))?;
+            filetime::set_file_times(&part, filetime, filetime)
+                .map_err(|why| Error::FileTime(part.clone(), why))?;
         }
 
-        Ok(())
+        Ok(used)
+    }
+
+    /// Selects the transport which serves the URL's scheme.
+    fn transport(&self, url: &str) -> Result<&dyn Transport, Error> {
+        let scheme = transport::scheme(url);
+
+        if let Some(transport) = self.transports.get(scheme) {
+            return Ok(&**transport);
+        }
+
+        match scheme {
+            "http" | "https" => Ok(&self.client),
+            "file" => Ok(&FileTransport),
+            "data" => Ok(&DataTransport),
+            _ => Err(Error::UnsupportedScheme(scheme.into())),
+        }
     }
 
     fn cancelled(&self) -> bool {
         self.cancel.as_ref().map_or(false, |cancel| cancel.load(Ordering::SeqCst))
     }
 
-    fn send(&self, event: (Arc<Path>, FetchEvent)) {
-        if let Some(sender) = self.events.as_ref() {
-            let _ = sender.unbounded_send(event);
+    async fn send(&self, (path, event): (Arc<Path>, FetchEvent)) {
+        if let Some(sink) = self.events.as_ref() {
+            sink.send((self.alias(path), event)).await;
+        }
+    }
+
+    async fn send_batch(&self, event: BatchEvent) {
+        if let Some(sink) = self.events.as_ref() {
+            sink.send_batch(event).await;
         }
     }
+
+    /// The path which the events of a path are reported under.
+    fn alias(&self, path: Arc<Path>) -> Arc<Path> {
+        let aliases = self.aliases.lock().expect("aliases are poisoned");
+        aliases.get(&path).cloned().unwrap_or(path)
+    }
 }
 
-fn content_length(headers: &Headers) -> Option<u64> {
-    headers.get("content-length").and_then(|header| header.parse::<u64>().ok())
+/// The default location of the partial file: a hidden sibling of the destination.
+fn partial_path(to: &Path) -> Result<Arc<Path>, Error> { hidden_sibling(to, "partial") }
+
+/// A hidden sibling of a file, which is named after it with the given extension.
+fn hidden_sibling(to: &Path, extension: &str) -> Result<Arc<Path>, Error> {
+    let parent = to.parent().ok_or(Error::Parentless)?;
+    let filename = to.file_name().ok_or(Error::Nameless)?;
+
+    let mut sibling = OsString::from(".");
+    sibling.push(filename);
+    sibling.push(".");
+    sibling.push(extension);
+
+    Ok(parent.join(sibling).into())
 }
 
-fn last_modified(headers: &Headers) -> Option<DateTime<Utc>> {
-    headers
-        .get("last-modified")
-        .and_then(|header| DateTime::parse_from_rfc2822(header).ok())
-        .map(|tz| tz.with_timezone(&Utc))
+/// Checks if an existing file is the version that a response describes: by its record,
+/// if it has one, or else by its length and modification time.
+fn is_up_to_date(
+    head: &ResponseHead,
+    metadata: &fs::Metadata,
+    record: Option<&Record>,
+) -> bool {
+    if let Some(record) = record {
+        return record.is_current(head, metadata.len());
+    }
+
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok());
+
+    match (head.content_length(), head.last_modified(), modified) {
+        (Some(length), Some(last_modified), Some(modified)) => {
+            metadata.len() == length
+                && modified.as_secs() == last_modified.timestamp() as u64
+        }
+        _ => false,
+    }
 }
 
-async fn head<C: HttpClient>(
-    client: &Client<C>,
-    uri: &str,
-) -> Result<Option<Response>, Error> {
-    match validate(client.head(uri).set_header("Expect", "").await?).map(Some) {
-        result @ Ok(_) => result,
-        Err(Error::Status(StatusCode::NOT_IMPLEMENTED)) => Ok(None),
-        Err(other) => Err(other),
+/// Checks if an existing file matches its checksum.
+async fn verify_existing(path: &Path, checksum: &Checksum) -> bool {
+    let buffer = &mut [0u8; 8 * 1024];
+    match File::open(path).await {
+        Ok(file) => checksum.validate(file, buffer).await.is_ok(),
+        Err(_) => false,
     }
 }
 
-async fn supports_range<C: HttpClient>(
-    client: &Client<C>,
-    uri: &str,
-    length: u64,
-) -> Result<bool, Error> {
-    let response = client
-        .head(uri)
-        .set_header("Expect", "")
-        .set_header("range", range::to_string(0, length).as_str())
-        .await?;
-
-    if response.status() == StatusCode::PARTIAL_CONTENT {
-        Ok(true)
-    } else {
-        validate(response).map(|_| false)
+/// How the parts of a file are written into its partial file.
+enum Assembly<'a> {
+    /// Parts are appended in the order of their ranges, and fed to the hasher.
+    Concatenate(Option<&'a mut Hasher>),
+    /// Each part is written at the offset that its range begins at, as it arrives.
+    Splice,
+}
+
+/// Writes a fetched part into the file at the given offset, and removes the part.
+async fn splice(file: &mut File, offset: u64, part: Arc<Path>) -> Result<(), Error> {
+    let mut reader = File::open(part.as_ref())
+        .await
+        .map_err(|why| Error::OpenPart(part.clone(), why))?;
+
+    file.seek(SeekFrom::Start(offset)).await.map_err(Error::Concatenate)?;
+    async_std::io::copy(&mut reader, file).await.map_err(Error::Concatenate)?;
+
+    if let Err(why) = fs::remove_file(part.as_ref()).await {
+        error!("failed to remove part file ({:?}): {}", part, why);
     }
+
+    Ok(())
+}
+
+/// Flushes a file, or the entries of a directory, to the disk.
+async fn sync(path: &Arc<Path>) -> Result<(), Error> {
+    let file =
+        File::open(path.as_ref()).await.map_err(|why| Error::Sync(path.clone(), why))?;
+    file.sync_all().await.map_err(|why| Error::Sync(path.clone(), why))
+}
+
+/// Reorders the mirrors so that the list begins at the given offset.
+fn rotate(uris: &[Box<str>], offset: usize) -> Arc<[Box<str>]> {
+    let offset = offset % uris.len();
+    uris[offset..].iter().chain(&uris[..offset]).cloned().collect()
 }
 
 async fn timed<F, T>(duration: Duration, future: F) -> Result<T, Error>
@@ -481,13 +1805,3 @@ where
 {
     async_std::future::timeout(duration, future).await.map_err(|_| Error::TimedOut)
 }
-
-fn validate(response: Response) -> Result<Response, Error> {
-    let status = response.status();
-
-    if status.as_u16() < 300 {
-        Ok(response)
-    } else {
-        Err(Error::Status(status))
-    }
-}
@@ -0,0 +1,352 @@
+//! Support for Metalink documents, as described by RFC 5854.
+//!
+//! A Metalink describes each file by its mirrors, size, whole-file hashes, and piece
+//! hashes, which are converted into `Source`s that can be given to the fetcher.
+
+use crate::{
+    checksum::{Checksum, Pieces, SumStr},
+    Source,
+};
+use roxmltree::{Document, Node};
+use std::{
+    convert::TryFrom,
+    num::NonZeroU64,
+    path::{Component, Path},
+};
+
+/// The namespace of Metalink 4 documents.
+const NAMESPACE: &str = "urn:ietf:params:xml:ns:metalink";
+
+/// Mirrors without a priority are preferred the least.
+const LOWEST_PRIORITY: u32 = 999_999;
+
+#[derive(Debug, Error)]
+pub enum MetalinkError {
+    #[error("file element lacks a name")]
+    Nameless,
+    #[error("file {} has an invalid {} hash", _0, _1)]
+    InvalidHash(Box<str>, Box<str>),
+    #[error("file {} has an invalid piece length", _0)]
+    InvalidPieceLength(Box<str>),
+    #[error("file {} has an invalid size", _0)]
+    InvalidSize(Box<str>),
+    #[error("document has no metalink element")]
+    NotMetalink,
+    #[error("file {} has no URLs", _0)]
+    NoUrls(Box<str>),
+    #[error("file name {} escapes the destination directory", _0)]
+    UnsafeName(Box<str>),
+    #[error("invalid XML")]
+    Xml(#[from] roxmltree::Error),
+}
+
+/// A file described by a Metalink document.
+#[derive(Debug)]
+pub struct MetalinkFile {
+    /// The source which fetches the file, with its mirrors ordered by priority.
+    pub source: Source,
+
+    /// The length of the file, if it was declared.
+    pub size: Option<u64>,
+}
+
+/// Parses a Metalink document, placing each file it describes within `dir`.
+///
+/// Mirrors are ordered from the highest priority to the lowest. The strongest
+/// supported hash is used as the checksum of each file, and pieces are only attached
+/// when their hashes use a supported algorithm.
+///
+/// Names may place files within subdirectories of `dir`. Those directories are not
+/// created here, and must exist before the files are fetched.
+pub fn parse(document: &str, dir: &Path) -> Result<Vec<MetalinkFile>, MetalinkError> {
+    let document = Document::parse(document)?;
+    let root = document.root_element();
+
+    if !is(root, "metalink") {
+        return Err(MetalinkError::NotMetalink);
+    }
+
+    root.children()
+        .filter(|node| is(*node, "file"))
+        .map(|file| parse_file(file, dir))
+        .collect()
+}
+
+fn parse_file(file: Node, dir: &Path) -> Result<MetalinkFile, MetalinkError> {
+    let name = file.attribute("name").ok_or(MetalinkError::Nameless)?;
+
+    // Names are relative paths which must not escape the destination directory.
+    let relative = Path::new(name);
+    if !relative.components().all(|component| matches!(component, Component::Normal(_))) {
+        return Err(MetalinkError::UnsafeName(name.into()));
+    }
+
+    let mut urls = file
+        .children()
+        .filter(|node| is(*node, "url"))
+        .filter_map(|node| {
+            let url = node.text()?.trim();
+            let priority = node
+                .attribute("priority")
+                .and_then(|priority| priority.parse::<u32>().ok())
+                .unwrap_or(LOWEST_PRIORITY);
+
+            Some((priority, Box::from(url)))
+        })
+        .collect::<Vec<(u32, Box<str>)>>();
+
+    if urls.is_empty() {
+        return Err(MetalinkError::NoUrls(name.into()));
+    }
+
+    urls.sort_by_key(|(priority, _)| *priority);
+
+    let size = match child(file, "size").and_then(|node| node.text()) {
+        Some(size) => Some(
+            size.trim()
+                .parse::<u64>()
+                .map_err(|_| MetalinkError::InvalidSize(name.into()))?,
+        ),
+        None => None,
+    };
+
+    let urls = urls.into_iter().map(|(_, url)| url).collect::<Vec<Box<str>>>();
+    let mut source = Source::new(urls, dir.join(relative));
+
+    let hashes = file.children().filter(|node| is(*node, "hash"));
+    if let Some(checksum) = strongest(name, hashes)? {
+        source = source.checksum(checksum);
+    }
+
+    // Pieces are taken from the first list whose hashes are of a supported type.
+    for pieces in file.children().filter(|node| is(*node, "pieces")) {
+        if let Some(pieces) = parse_pieces(name, pieces)? {
+            source = source.pieces(pieces);
+            break;
+        }
+    }
+
+    Ok(MetalinkFile { source, size })
+}
+
+fn parse_pieces(name: &str, pieces: Node) -> Result<Option<Pieces>, MetalinkError> {
+    let kind = pieces.attribute("type").unwrap_or_default();
+
+    let size = pieces
+        .attribute("length")
+        .and_then(|length| length.parse::<u64>().ok())
+        .and_then(NonZeroU64::new)
+        .ok_or_else(|| MetalinkError::InvalidPieceLength(name.into()))?;
+
+    let mut checksums = Vec::new();
+    for hash in pieces.children().filter(|node| is(*node, "hash")) {
+        let digest = hash.text().unwrap_or_default().trim();
+        match checksum(kind, digest) {
+            Some(Ok(checksum)) => checksums.push(checksum),
+            Some(Err(())) => return Err(invalid_hash(name, kind)),
+            None => return Ok(None),
+        }
+    }
+
+    Ok(Some(Pieces::new(size, checksums)))
+}
+
+/// Selects the strongest of the supported hashes.
+fn strongest<'a, 'input: 'a>(
+    name: &str,
+    hashes: impl Iterator<Item = Node<'a, 'input>>,
+) -> Result<Option<Checksum>, MetalinkError> {
+    let mut strongest: Option<(u8, Checksum)> = None;
+
+    for hash in hashes {
+        let kind = hash.attribute("type").unwrap_or_default();
+        let digest = hash.text().unwrap_or_default().trim();
+
+        let checksum = match checksum(kind, digest) {
+            Some(Ok(checksum)) => checksum,
+            Some(Err(())) => return Err(invalid_hash(name, kind)),
+            None => continue,
+        };
+
+        let strength = match checksum {
+            Checksum::Md5(_) => 0,
+            Checksum::Sha256(_) => 1,
+        };
+
+        if !matches!(strongest, Some((current, _)) if current >= strength) {
+            strongest = Some((strength, checksum));
+        }
+    }
+
+    Ok(strongest.map(|(_, checksum)| checksum))
+}
+
+/// Converts a hash into a checksum, or `None` if the algorithm is not supported.
+fn checksum(kind: &str, digest: &str) -> Option<Result<Checksum, ()>> {
+    let sum = match kind.to_ascii_lowercase().as_str() {
+        "md5" => SumStr::Md5(digest),
+        "sha-256" => SumStr::Sha256(digest),
+        _ => return None,
+    };
+
+    Some(Checksum::try_from(sum).map_err(|_| ()))
+}
+
+fn invalid_hash(name: &str, kind: &str) -> MetalinkError {
+    MetalinkError::InvalidHash(name.into(), kind.into())
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|child| is(*child, name))
+}
+
+fn is(node: Node, name: &str) -> bool {
+    node.is_element()
+        && node.tag_name().name() == name
+        && matches!(node.tag_name().namespace(), None | Some(NAMESPACE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MD5: &str = "d41d8cd98f00b204e9800998ecf8427e";
+    const SHA1: &str = "da39a3ee5e6b4b0d3255bfef95601890afd80709";
+    const SHA256: &str =
+        "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+    fn metalink(files: &str) -> String {
+        format!(
+            r#"<?xml version="1.0"?><metalink xmlns="{}">{}</metalink>"#,
+            NAMESPACE, files
+        )
+    }
+
+    fn single(document: &str) -> MetalinkFile {
+        let mut files = parse(document, Path::new("/dir")).unwrap();
+        assert_eq!(files.len(), 1);
+        files.remove(0)
+    }
+
+    fn urls(file: &MetalinkFile) -> Vec<&str> {
+        file.source.urls.iter().map(AsRef::as_ref).collect()
+    }
+
+    #[test]
+    fn orders_mirrors_by_priority() {
+        let file = single(&metalink(
+            r#"<file name="a">
+                <url>http://unprioritized/a</url>
+                <url priority="2">http://second/a</url>
+                <url priority="1">http://first/a</url>
+                <size>42</size>
+            </file>"#,
+        ));
+
+        assert_eq!(
+            urls(&file),
+            ["http://first/a", "http://second/a", "http://unprioritized/a"]
+        );
+        assert_eq!(file.size, Some(42));
+        assert_eq!(file.source.dest.as_ref(), Path::new("/dir/a"));
+    }
+
+    #[test]
+    fn accepts_unqualified_elements_and_ignores_foreign_ones() {
+        let file = single(
+            r#"<metalink>
+                <file name="a">
+                    <url>http://mirror/a</url>
+                    <other:url xmlns:other="urn:example">http://foreign/a</other:url>
+                </file>
+            </metalink>"#,
+        );
+
+        assert_eq!(urls(&file), ["http://mirror/a"]);
+
+        let foreign = r#"<metalink xmlns="urn:example"><file name="a"/></metalink>"#;
+        assert!(matches!(
+            parse(foreign, Path::new("/dir")),
+            Err(MetalinkError::NotMetalink)
+        ));
+    }
+
+    #[test]
+    fn rejects_names_which_escape_the_directory() {
+        for name in &["../a", "/etc/a", "sub/../../a", "./a"] {
+            let file =
+                format!(r#"<file name="{}"><url>http://mirror/a</url></file>"#, name);
+            let result = parse(&metalink(&file), Path::new("/dir"));
+            assert!(matches!(result, Err(MetalinkError::UnsafeName(_))), "{}", name);
+        }
+
+        let file =
+            single(&metalink(r#"<file name="sub/a"><url>http://mirror/a</url></file>"#));
+        assert_eq!(file.source.dest.as_ref(), Path::new("/dir/sub/a"));
+    }
+
+    #[test]
+    fn selects_the_strongest_supported_hash() {
+        let file = single(&metalink(&format!(
+            r#"<file name="a">
+                <url>http://mirror/a</url>
+                <hash type="md5">{}</hash>
+                <hash type="sha-256">{}</hash>
+                <hash type="sha-512">unsupported</hash>
+                <hash type="md5">{}</hash>
+            </file>"#,
+            MD5, SHA256, MD5
+        )));
+
+        assert!(matches!(file.source.checksum.as_deref(), Some(Checksum::Sha256(_))));
+
+        let file = single(&metalink(
+            r#"<file name="a"><url>http://a</url><hash type="sha-1">00</hash></file>"#,
+        ));
+
+        assert!(file.source.checksum.is_none());
+
+        let invalid = metalink(
+            r#"<file name="a"><url>http://a</url><hash type="md5">00</hash></file>"#,
+        );
+        assert!(matches!(
+            parse(&invalid, Path::new("/dir")),
+            Err(MetalinkError::InvalidHash(..))
+        ));
+    }
+
+    #[test]
+    fn attaches_pieces_only_of_a_supported_type() {
+        let pieces = |kind: &str, digest: &str| {
+            metalink(&format!(
+                r#"<file name="a">
+                    <url>http://mirror/a</url>
+                    <pieces type="{}" length="1024"><hash>{}</hash><hash>{}</hash></pieces>
+                </file>"#,
+                kind, digest, digest
+            ))
+        };
+
+        let file = single(&pieces("sha-256", SHA256));
+        let attached = file.source.pieces.as_deref().expect("pieces were dropped");
+        assert_eq!(attached.size.get(), 1024);
+        assert_eq!(attached.checksums.len(), 2);
+
+        let file = single(&pieces("sha-1", SHA1));
+        assert!(file.source.pieces.is_none());
+
+        // A supported list is found after one which is not.
+        let file = single(&metalink(&format!(
+            r#"<file name="a">
+                <url>http://mirror/a</url>
+                <pieces type="sha-1" length="512"><hash>{}</hash></pieces>
+                <pieces type="sha-256" length="1024"><hash>{}</hash></pieces>
+            </file>"#,
+            SHA1, SHA256
+        )));
+
+        let attached = file.source.pieces.as_deref().expect("pieces were dropped");
+        assert_eq!(attached.size.get(), 1024);
+        assert!(matches!(&*attached.checksums, [Checksum::Sha256(_)]));
+    }
+}
@@ -0,0 +1,200 @@
+//! Counters and histograms of the requests which a fetcher makes.
+//!
+//! A [`Metrics`] is given to a fetcher, which updates it as it fetches. Rendering it
+//! produces the Prometheus text exposition format, so that a service may serve it to
+//! whichever agent scrapes its metrics.
+
+use crate::Error;
+use std::{
+    collections::BTreeMap,
+    fmt::{Display, Write},
+    mem,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+/// Upper bounds of the request latency buckets, in seconds.
+const BUCKETS: [f64; 11] =
+    [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// Metrics which a fetcher updates as it fetches.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    state:  Mutex<State>,
+    active: AtomicU64,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// Bytes received from each host.
+    bytes: BTreeMap<Box<str>, u64>,
+
+    /// Time until the response to each request arrived, by host.
+    latency: BTreeMap<Box<str>, Histogram>,
+
+    /// Attempts which were made after a previous attempt failed.
+    retries: u64,
+
+    /// Attempts which failed, by the class of their error.
+    failures: BTreeMap<&'static str, u64>,
+}
+
+#[derive(Debug, Default)]
+struct Histogram {
+    buckets: [u64; BUCKETS.len()],
+    count:   u64,
+    sum:     f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: f64) {
+        for (bucket, &bound) in self.buckets.iter_mut().zip(BUCKETS.iter()) {
+            if value <= bound {
+                *bucket += 1;
+            }
+        }
+
+        self.count += 1;
+        self.sum += value;
+    }
+}
+
+/// Counts a request as active until it is dropped.
+pub(crate) struct Connection<'a>(&'a Metrics);
+
+impl Drop for Connection<'_> {
+    fn drop(&mut self) { self.0.active.fetch_sub(1, Ordering::SeqCst); }
+}
+
+impl Metrics {
+    /// Renders every metric in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let state = self.state.lock().expect("metrics are poisoned");
+        let out = &mut String::new();
+
+        header(out, "bytes_total", "counter", "Bytes received, by host.");
+        for (host, bytes) in &state.bytes {
+            sample(out, "bytes_total", &labels(host, None), bytes);
+        }
+
+        let help = "Time until the response to a request arrived, by host.";
+        header(out, "request_duration_seconds", "histogram", help);
+        for (host, histogram) in &state.latency {
+            let (count, sum) = (histogram.count, histogram.sum);
+
+            for (bucket, bound) in histogram.buckets.iter().zip(BUCKETS.iter()) {
+                let bucket_labels = labels(host, Some(&bound.to_string()));
+                sample(out, "request_duration_seconds_bucket", &bucket_labels, bucket);
+            }
+
+            let bucket_labels = labels(host, Some("+Inf"));
+            sample(out, "request_duration_seconds_bucket", &bucket_labels, count);
+            sample(out, "request_duration_seconds_sum", &labels(host, None), sum);
+            sample(out, "request_duration_seconds_count", &labels(host, None), count);
+        }
+
+        let help = "Attempts made after an attempt failed.";
+        header(out, "retries_total", "counter", help);
+        sample(out, "retries_total", "", state.retries);
+
+        header(out, "failures_total", "counter", "Failed attempts, by class of error.");
+        for (class, failures) in &state.failures {
+            sample(out, "failures_total", &format!("{{class=\"{}\"}}", class), failures);
+        }
+
+        header(out, "active_connections", "gauge", "Requests which are in progress.");
+        sample(out, "active_connections", "", self.active.load(Ordering::SeqCst));
+
+        mem::take(out)
+    }
+
+    /// Counts a request as active, until the returned guard is dropped.
+    pub(crate) fn connect(&self) -> Connection<'_> {
+        self.active.fetch_add(1, Ordering::SeqCst);
+        Connection(self)
+    }
+
+    /// Records the time that a request to the URL took to be responded to.
+    pub(crate) fn responded(&self, url: &str, latency: Duration) {
+        let mut state = self.state.lock().expect("metrics are poisoned");
+        let histogram = state.latency.entry(host(url).into()).or_default();
+        histogram.observe(latency.as_secs_f64());
+    }
+
+    /// Records bytes which were received from the URL.
+    pub(crate) fn received(&self, url: &str, bytes: u64) {
+        let mut state = self.state.lock().expect("metrics are poisoned");
+        *state.bytes.entry(host(url).into()).or_default() += bytes;
+    }
+
+    /// Records an attempt which failed, and whether another attempt will be made.
+    pub(crate) fn failed(&self, error: &Error, retrying: bool) {
+        let mut state = self.state.lock().expect("metrics are poisoned");
+        *state.failures.entry(class(error)).or_default() += 1;
+        if retrying {
+            state.retries += 1;
+        }
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP async_fetcher_{} {}", name, help);
+    let _ = writeln!(out, "# TYPE async_fetcher_{} {}", name, kind);
+}
+
+fn sample(out: &mut String, name: &str, labels: &str, value: impl Display) {
+    let _ = writeln!(out, "async_fetcher_{}{} {}", name, labels, value);
+}
+
+/// The labels of a sample for a host, and for the bucket of a histogram.
+fn labels(host: &str, bucket: Option<&str>) -> String {
+    let host = host.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n");
+    match bucket {
+        Some(bound) => format!("{{host=\"{}\",le=\"{}\"}}", host, bound),
+        None => format!("{{host=\"{}\"}}", host),
+    }
+}
+
+/// The host of a URL, or an empty string for one which has none, such as a local path.
+fn host(url: &str) -> &str {
+    let rest = match url.find("://") {
+        Some(pos) => &url[pos + 3..],
+        None => return "",
+    };
+
+    let end = rest.find(&['/', '?', '#'][..]).unwrap_or(rest.len());
+    let authority = &rest[..end];
+
+    // Credentials are not to be exposed as labels.
+    authority.rsplit('@').next().unwrap_or(authority)
+}
+
+/// A coarse description of what caused an error, to label failures with.
+fn class(error: &Error) -> &'static str {
+    match error.inner() {
+        Error::Checksum(_) | Error::DeltaChecksum | Error::PieceChecksum { .. } => {
+            "checksum"
+        }
+        Error::Client(_) => "client",
+        Error::ContentRange { .. } | Error::UnexpectedLength { .. } => "response",
+        Error::InsufficientSpace { .. } | Error::SpaceQuery(_) => "space",
+        Error::Rejected(_) => "rejected",
+        Error::Status(..) => "status",
+        Error::TimedOut => "timeout",
+        Error::Concatenate(_)
+        | Error::FileCreate(_)
+        | Error::FileTime(..)
+        | Error::Local(..)
+        | Error::MetadataRemove(_)
+        | Error::OpenPart(..)
+        | Error::Record(..)
+        | Error::Rename(_)
+        | Error::Seed(..)
+        | Error::Sync(..)
+        | Error::Write(_) => "io",
+        _ => "other",
+    }
+}
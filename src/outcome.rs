@@ -0,0 +1,51 @@
+use crate::record::Validators;
+use std::time::Duration;
+
+/// How the destination of a fetch was brought up to date.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FetchStatus {
+    /// The file was fetched in full.
+    Downloaded,
+    /// The server reported that the destination had not been modified.
+    NotModified,
+    /// The destination was found to be up to date without fetching it.
+    AlreadyFetched,
+    /// A partial file left by an interrupted fetch was completed.
+    Resumed,
+}
+
+/// What was done to fetch a source.
+#[derive(Clone, Debug, Setters)]
+pub struct FetchOutcome {
+    /// How the destination was brought up to date.
+    #[setters(skip)]
+    pub status: FetchStatus,
+
+    /// The number of bytes that were transferred.
+    pub bytes: u64,
+
+    /// The time spent on the fetch, across every attempt.
+    pub elapsed: Duration,
+
+    /// The URLs which were used by the attempt that succeeded.
+    pub urls: Vec<Box<str>>,
+
+    /// The number of attempts which failed before one succeeded.
+    pub retries: u16,
+
+    /// Validators of the final response, such as its ETag and Last-Modified.
+    pub validators: Validators,
+}
+
+impl FetchOutcome {
+    pub(crate) fn new(status: FetchStatus) -> Self {
+        Self {
+            status,
+            bytes: 0,
+            elapsed: Duration::default(),
+            urls: Vec::new(),
+            retries: 0,
+            validators: Validators::default(),
+        }
+    }
+}
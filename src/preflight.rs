@@ -0,0 +1,164 @@
+//! Checks of a batch of sources, which are made before any of them is fetched.
+//!
+//! Each source is checked in the same way that a fetch decides whether its destination
+//! is up to date, but nothing is written: records are not updated, and the cache is
+//! only consulted. The lengths of the remaining sources give the size of the batch.
+
+use crate::{
+    is_up_to_date, record::Record, verify_existing, BatchEvent, Error, Fetcher,
+    RequestContext, Source,
+};
+use async_std::fs;
+use futures::stream::{self, StreamExt};
+use std::{num::NonZeroU16, path::Path, sync::Arc};
+use surf::middleware::HttpClient;
+
+/// What a fetch of a source would do.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PreflightStatus {
+    /// The destination is up to date, and would not be fetched.
+    UpToDate,
+    /// The content would be taken from the cache, rather than fetched.
+    Cached,
+    /// The source would be fetched.
+    Stale,
+}
+
+/// The result of checking a source.
+#[derive(Debug)]
+pub struct Preflight {
+    /// The destination of the source.
+    pub dest: Arc<Path>,
+
+    /// What a fetch of the source would do, or why it could not be checked.
+    pub status: Result<PreflightStatus, Error>,
+
+    /// The length of the source, if it is known.
+    pub length: Option<u64>,
+}
+
+/// The results of checking a batch of sources.
+#[derive(Debug, Default)]
+pub struct PreflightReport {
+    /// The result of each check, in the order that the sources were given.
+    pub sources: Vec<Preflight>,
+
+    /// The number of bytes which would be fetched, across sources of known length.
+    pub bytes: u64,
+
+    /// The number of sources which would be fetched.
+    pub stale: usize,
+
+    /// The number of sources which would be fetched, but whose lengths are unknown.
+    pub unknown: usize,
+
+    /// The number of sources which are up to date, or would be taken from the cache.
+    pub up_to_date: usize,
+
+    /// The number of sources which could not be checked.
+    pub failed: usize,
+}
+
+impl<C: HttpClient> Fetcher<C> {
+    /// Checks which sources are up to date, and how much of the rest would be fetched,
+    /// without fetching any of them. Up to `concurrent` sources are checked at a time.
+    ///
+    /// Once every source is checked, `BatchEvent::Size` is sent with the totals.
+    pub async fn preflight(
+        self: Arc<Self>,
+        sources: &[Source],
+        concurrent: NonZeroU16,
+    ) -> PreflightReport {
+        let checks = stream::iter(sources)
+            .map(|source| {
+                let fetcher = self.clone();
+                async move {
+                    let (status, length) = match fetcher.check(source).await {
+                        Ok((status, length)) => (Ok(status), length),
+                        Err(why) => {
+                            let context = RequestContext::new(&source.urls[0]);
+                            (Err(why.within(context)), None)
+                        }
+                    };
+
+                    Preflight { dest: source.dest.clone(), status, length }
+                }
+            })
+            .buffered(concurrent.get() as usize);
+
+        let mut report =
+            PreflightReport { sources: checks.collect().await, ..Default::default() };
+
+        for source in &report.sources {
+            match source.status {
+                Ok(PreflightStatus::Stale) => {
+                    report.stale += 1;
+                    match source.length {
+                        Some(length) => report.bytes += length,
+                        None => report.unknown += 1,
+                    }
+                }
+                Ok(_) => report.up_to_date += 1,
+                Err(_) => report.failed += 1,
+            }
+        }
+
+        let size = BatchEvent::Size { files: report.stale, bytes: report.bytes };
+        self.send_batch(size).await;
+
+        report
+    }
+
+    /// Determines what a fetch of the source would do, and the length of the source.
+    async fn check(
+        &self,
+        source: &Source,
+    ) -> Result<(PreflightStatus, Option<u64>), Error> {
+        let to = &source.dest;
+        let url = &source.urls[0];
+        let checksum = source.checksum.as_deref();
+        let mut metadata = fs::metadata(to.as_ref()).await.ok();
+
+        // A file which fails its checksum would be fetched in full.
+        if let (Some(existing), Some(checksum)) = (metadata.as_ref(), checksum) {
+            if verify_existing(to, checksum).await {
+                return Ok((PreflightStatus::UpToDate, Some(existing.len())));
+            }
+
+            metadata = None;
+        }
+
+        if let (None, Some(cache), Some(checksum)) =
+            (metadata.as_ref(), self.cache.as_ref(), checksum)
+        {
+            if cache.entry(checksum).exists() {
+                return Ok((PreflightStatus::Cached, None));
+            }
+        }
+
+        let record = match (metadata.as_ref(), self.records) {
+            (Some(_), true) => Record::load(to).await,
+            _ => None,
+        };
+
+        if let (Some(record), Some(existing)) = (record.as_ref(), metadata.as_ref()) {
+            if !source.revalidate && record.is_fresh(existing.len()) {
+                return Ok((PreflightStatus::UpToDate, Some(existing.len())));
+            }
+        }
+
+        let head = self.transport(url)?.probe(url).await?;
+        let length = head
+            .as_ref()
+            .and_then(|head| head.content_length())
+            .or_else(|| source.delta.as_ref().map(|delta| delta.control.length));
+
+        if let (Some(head), Some(existing)) = (head.as_ref(), metadata.as_ref()) {
+            if is_up_to_date(head, existing, record.as_ref()) {
+                return Ok((PreflightStatus::UpToDate, Some(existing.len())));
+            }
+        }
+
+        Ok((PreflightStatus::Stale, length))
+    }
+}
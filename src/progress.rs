@@ -0,0 +1,256 @@
+//! Progress of a batch of fetches, aggregated from their events.
+//!
+//! A [`ProgressTracker`] accounts for the events of every source, and periodically
+//! produces a [`BatchProgress`] with the totals of the batch, so that each frontend
+//! need not keep its own tallies of bytes, files and transfer speeds.
+
+use crate::{BatchEvent, FetchEvent};
+use std::{
+    collections::{HashMap, VecDeque},
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// The span of time which the transfer speed is averaged over.
+const SPEED_WINDOW: Duration = Duration::from_secs(5);
+
+/// The progress of a single file.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FileProgress {
+    /// The number of bytes of the file which are done.
+    pub bytes: u64,
+
+    /// The length of the file, once it is known.
+    pub length: Option<u64>,
+}
+
+/// Totals across every source which the tracker has seen.
+#[derive(Clone, Debug, Default)]
+pub struct BatchProgress {
+    /// The number of bytes which are done.
+    pub bytes: u64,
+
+    /// The number of bytes which the batch is known to consist of.
+    pub length: u64,
+
+    /// The number of sources which a preflight check found the batch would fetch.
+    pub files: usize,
+
+    /// The number of bytes transferred per second, averaged over the last few seconds.
+    pub speed: u64,
+
+    /// The estimated time until every known byte is done.
+    pub eta: Option<Duration>,
+
+    /// The time since the first event of the batch.
+    pub elapsed: Duration,
+
+    /// The number of sources which are being fetched.
+    pub fetching: usize,
+
+    /// The number of sources which were fetched, or found to be up to date.
+    pub fetched: usize,
+
+    /// The number of sources which failed to be fetched.
+    pub failed: usize,
+
+    /// The number of sources whose fetch was cancelled.
+    pub cancelled: usize,
+}
+
+/// Aggregates the events of a batch of fetches into totals for the whole batch.
+pub struct ProgressTracker {
+    interval: Duration,
+    state:    Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    started:     Option<Instant>,
+    last_update: Option<Instant>,
+    files:       HashMap<Arc<Path>, File>,
+    batch:       BatchProgress,
+
+    /// Lengths of the sources which were fetched, or are being fetched.
+    known: u64,
+
+    /// The length of the batch, according to a preflight check.
+    expected: u64,
+
+    /// Bytes which were transferred, including those of failed attempts.
+    transferred: u64,
+
+    /// Bytes transferred, as of each recent event.
+    samples: VecDeque<(Instant, u64)>,
+}
+
+#[derive(Default)]
+struct File {
+    progress: FileProgress,
+
+    /// Bytes of a resumed partial file, which are reported as progress but were not
+    /// transferred.
+    resumed: u64,
+}
+
+impl ProgressTracker {
+    /// Creates a tracker which produces an update at most once per interval.
+    pub fn new(interval: Duration) -> Self { Self { interval, state: Mutex::default() } }
+
+    /// Accounts for an event of a source.
+    ///
+    /// Returns the totals of the batch if the interval has passed since the previous
+    /// update, or if the event ends the fetch of a source.
+    pub fn track(&self, dest: &Arc<Path>, event: &FetchEvent) -> Option<BatchProgress> {
+        let now = Instant::now();
+        let state = &mut *self.state.lock().expect("progress tracker is poisoned");
+        let started = *state.started.get_or_insert(now);
+
+        let ended = match event {
+            FetchEvent::Fetching => {
+                state.files.insert(dest.clone(), File::default());
+                state.batch.fetching += 1;
+                false
+            }
+            FetchEvent::ContentLength(length) => {
+                let file = state.files.entry(dest.clone()).or_default();
+                let previous = file.progress.length.replace(*length).unwrap_or(0);
+                state.known = state.known - previous + length;
+                false
+            }
+            FetchEvent::Progress(read) => {
+                let read = *read as u64;
+                let file = state.files.entry(dest.clone()).or_default();
+                let resumed = file.resumed.min(read);
+
+                file.progress.bytes += read;
+                file.resumed -= resumed;
+                state.batch.bytes += read;
+                state.transferred += read - resumed;
+                false
+            }
+            FetchEvent::Resumed(offset) => {
+                state.files.entry(dest.clone()).or_default().resumed = *offset;
+                false
+            }
+            // Whatever the failed attempt had written will be fetched again.
+            FetchEvent::Retrying { .. } => {
+                if let Some(file) = state.files.get_mut(dest) {
+                    state.batch.bytes -= file.progress.bytes;
+                    file.progress.bytes = 0;
+                    file.resumed = 0;
+                }
+
+                false
+            }
+            // The bytes of a corrupted piece will be fetched again.
+            FetchEvent::PartRetrying { bytes, .. } => {
+                if let Some(file) = state.files.get_mut(dest) {
+                    let discarded = file.progress.bytes.min(*bytes);
+                    state.batch.bytes -= discarded;
+                    file.progress.bytes -= discarded;
+                }
+
+                false
+            }
+            FetchEvent::Fetched | FetchEvent::AlreadyFetched => {
+                state.finish(dest);
+                state.batch.fetched += 1;
+                true
+            }
+            FetchEvent::Failed(_) => {
+                state.finish(dest);
+                state.batch.failed += 1;
+                true
+            }
+            FetchEvent::Cancelled => {
+                state.finish(dest);
+                state.batch.cancelled += 1;
+                true
+            }
+            _ => false,
+        };
+
+        let transferred = state.transferred;
+        state.samples.push_back((now, transferred));
+        while state.samples.len() > 2 && now - state.samples[0].0 > SPEED_WINDOW {
+            state.samples.pop_front();
+        }
+
+        let due = !matches!(state.last_update, Some(last) if now - last < self.interval);
+        if !ended && !due {
+            return None;
+        }
+
+        state.last_update = Some(now);
+        state.update(now, started);
+        Some(state.batch.clone())
+    }
+
+    /// Accounts for an event of the whole batch.
+    pub fn track_batch(&self, event: &BatchEvent) {
+        let state = &mut *self.state.lock().expect("progress tracker is poisoned");
+
+        match event {
+            BatchEvent::Size { files, bytes } => {
+                state.batch.files = *files;
+                state.expected = *bytes;
+                state.batch.length = state.known.max(state.expected);
+            }
+        }
+    }
+
+    /// The totals of the batch, as of the last event.
+    pub fn progress(&self) -> BatchProgress {
+        let state = &mut *self.state.lock().expect("progress tracker is poisoned");
+        if let Some(started) = state.started {
+            state.update(Instant::now(), started);
+        }
+
+        state.batch.clone()
+    }
+
+    /// The progress of a source which is being fetched.
+    pub fn file(&self, dest: &Path) -> Option<FileProgress> {
+        let state = self.state.lock().expect("progress tracker is poisoned");
+        state.files.get(dest).map(|file| file.progress)
+    }
+
+    /// The progress of every source which is being fetched.
+    pub fn files(&self) -> Vec<(Arc<Path>, FileProgress)> {
+        let state = self.state.lock().expect("progress tracker is poisoned");
+        state.files.iter().map(|(dest, file)| (dest.clone(), file.progress)).collect()
+    }
+}
+
+impl State {
+    /// Stops tracking a source whose fetch has ended.
+    fn finish(&mut self, dest: &Arc<Path>) {
+        if let Some(File { progress, .. }) = self.files.remove(dest) {
+            // The length of what was read may differ from the advertised length.
+            self.known = self.known - progress.length.unwrap_or(0) + progress.bytes;
+            self.batch.fetching = self.batch.fetching.saturating_sub(1);
+        }
+    }
+
+    /// Updates the elapsed time, transfer speed, and estimated time remaining.
+    fn update(&mut self, now: Instant, started: Instant) {
+        self.batch.elapsed = now - started;
+        self.batch.length = self.known.max(self.expected);
+
+        self.batch.speed = match self.samples.front() {
+            Some(&(since, from)) if now > since => {
+                let seconds = (now - since).as_secs_f64();
+                ((self.transferred - from) as f64 / seconds) as u64
+            }
+            _ => 0,
+        };
+
+        let remaining = self.batch.length.saturating_sub(self.batch.bytes);
+        self.batch.eta = match self.batch.speed {
+            0 => None,
+            speed => Some(Duration::from_secs(remaining / speed)),
+        };
+    }
+}
@@ -32,3 +32,15 @@ pub(crate) fn to_string(from: u64, to: u64) -> String {
     ["bytes=", from.numtoa_str(10, &mut from_a), "-", to.numtoa_str(10, &mut to_a)]
         .concat()
 }
+
+/// Parses the inclusive range of a `Content-Range` header, such as `bytes 0-499/1234`.
+pub(crate) fn parse_content_range(header: &str) -> Option<(u64, u64)> {
+    let range = header.trim().strip_prefix("bytes ")?;
+    let range = &range[..range.find('/')?];
+    let pos = range.find('-')?;
+
+    let start = range[..pos].trim().parse::<u64>().ok()?;
+    let end = range[pos + 1..].trim().parse::<u64>().ok()?;
+
+    Some((start, end))
+}
@@ -0,0 +1,158 @@
+//! Records of fetched files, which are kept in hidden sidecars beside them.
+//!
+//! A file's modification time is easily changed by anything which touches it, whereas
+//! its record retains the validators of the response that it was fetched from. These
+//! decide whether a file is already fetched, how it is revalidated, and whether a
+//! partial file may be resumed.
+
+use crate::{checksum::SumStrBuf, Error, ResponseHead};
+use async_std::fs;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// Identifies a version of a remote file, and how long it may be used without being
+/// revalidated.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Validators {
+    /// The entity tag of the response.
+    pub etag: Option<Box<str>>,
+
+    /// The time that the file was last modified, according to the server.
+    pub last_modified: Option<DateTime<Utc>>,
+
+    /// When the response stops being fresh, after which the file must be revalidated.
+    pub expires: Option<DateTime<Utc>>,
+}
+
+impl Validators {
+    pub fn from_head(head: &ResponseHead) -> Self {
+        Self {
+            etag:          head.etag().map(Box::from),
+            last_modified: head.last_modified(),
+            expires:       head.expires(),
+        }
+    }
+
+    /// Checks if a response describes the same version of the file.
+    ///
+    /// Entity tags are preferred when both have one.
+    pub fn matches(&self, head: &ResponseHead) -> bool {
+        match (self.etag.as_deref(), head.etag()) {
+            (Some(etag), Some(current)) => etag == current,
+            _ => {
+                self.last_modified.is_some() && self.last_modified == head.last_modified()
+            }
+        }
+    }
+}
+
+/// What is known of a file from when it was fetched.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Record {
+    /// The URL which the file was fetched from.
+    pub url: Box<str>,
+
+    #[serde(flatten)]
+    pub validators: Validators,
+
+    /// The length of the file.
+    pub length: Option<u64>,
+
+    /// The checksum which the file was verified against.
+    pub checksum: Option<SumStrBuf>,
+
+    /// When the file was fetched, or last found to be up to date.
+    pub fetched: DateTime<Utc>,
+}
+
+impl Record {
+    /// Checks if the file, having the given length, is the version that a response
+    /// describes.
+    pub fn is_current(&self, head: &ResponseHead, length: u64) -> bool {
+        self.length == Some(length)
+            && head.content_length().unwrap_or(length) == length
+            && self.validators.matches(head)
+    }
+
+    /// Checks if the file, having the given length, may be used without revalidation.
+    pub fn is_fresh(&self, length: u64) -> bool {
+        self.length == Some(length)
+            && matches!(self.validators.expires, Some(expires) if Utc::now() < expires)
+    }
+
+    /// Reads the record of a file, if it has a valid one.
+    pub async fn load(file: &Path) -> Option<Self> {
+        let sidecar = sidecar(file)?;
+        let data = fs::read(&sidecar).await.ok()?;
+
+        match serde_json::from_slice(&data) {
+            Ok(record) => Some(record),
+            Err(why) => {
+                warn!("ignoring invalid record {:?}: {}", sidecar, why);
+                None
+            }
+        }
+    }
+
+    /// Writes the record of a file, replacing the previous record.
+    pub async fn save(&self, file: &Path) -> Result<(), Error> {
+        let sidecar = sidecar(file).ok_or(Error::Nameless)?;
+        let record_error = |why| Error::Record(Arc::from(sidecar.as_path()), why);
+
+        let mut temporary = sidecar.clone().into_os_string();
+        temporary.push(".tmp");
+
+        let data = serde_json::to_vec(self).expect("records are always serializable");
+        fs::write(&temporary, data).await.map_err(record_error)?;
+        fs::rename(&temporary, &sidecar).await.map_err(record_error)
+    }
+
+    /// Removes the record of a file, if it has one.
+    pub async fn remove(file: &Path) {
+        if let Some(sidecar) = sidecar(file) {
+            let _ = fs::remove_file(sidecar).await;
+        }
+    }
+}
+
+/// The location of a file's record: a hidden sibling of the file.
+///
+/// Every name is prefixed, so that `name` and `.name` are given different records.
+fn sidecar(file: &Path) -> Option<PathBuf> {
+    let filename = file.file_name()?;
+
+    let mut sidecar = OsString::from(".");
+    sidecar.push(filename);
+    sidecar.push(".async-fetcher.meta");
+
+    Some(file.with_file_name(sidecar))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sidecars_are_distinct() {
+        let sidecars =
+            ["dir/bashrc", "dir/.bashrc", "dir/.foo.partial", "dir/foo.partial"]
+                .iter()
+                .map(|file| sidecar(Path::new(file)).unwrap())
+                .collect::<Vec<_>>();
+
+        assert_eq!(
+            sidecars,
+            [
+                Path::new("dir/.bashrc.async-fetcher.meta"),
+                Path::new("dir/..bashrc.async-fetcher.meta"),
+                Path::new("dir/..foo.partial.async-fetcher.meta"),
+                Path::new("dir/.foo.partial.async-fetcher.meta"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,155 @@
+use chrono::{DateTime, Duration, Utc};
+use http::StatusCode;
+use surf::Response;
+
+/// The status and headers of a response, captured before its body is read.
+#[derive(Clone, Debug)]
+pub struct ResponseHead {
+    /// The status code returned by the server.
+    pub status: StatusCode,
+
+    /// Every header in the response, in the order they were received.
+    pub headers: Box<[(Box<str>, Box<str>)]>,
+}
+
+impl ResponseHead {
+    pub(crate) fn from_response(response: &mut Response) -> Self {
+        let status = response.status();
+        let headers = response
+            .headers()
+            .iter()
+            .map(|(key, value)| (Box::from(key), Box::from(value)))
+            .collect();
+
+        Self { status, headers }
+    }
+
+    /// Fetches the value of a header, if it was set.
+    ///
+    /// Header names are compared case-insensitively.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_ref())
+    }
+
+    /// The length of the body, as reported by `Content-Length`.
+    pub fn content_length(&self) -> Option<u64> {
+        self.header("content-length").and_then(|header| header.parse::<u64>().ok())
+    }
+
+    /// When the response stops being fresh, as reported by `Cache-Control` or `Expires`.
+    ///
+    /// Responses which must always be revalidated are never fresh.
+    pub fn expires(&self) -> Option<DateTime<Utc>> {
+        if let Some(cache_control) = self.header("cache-control") {
+            let mut max_age = None;
+
+            for directive in cache_control.split(',') {
+                let mut directive = directive.splitn(2, '=');
+                let name = directive.next().unwrap_or_default().trim();
+                let value = directive.next().map(|value| value.trim().trim_matches('"'));
+
+                if name.eq_ignore_ascii_case("no-cache")
+                    || name.eq_ignore_ascii_case("no-store")
+                {
+                    return None;
+                }
+
+                if name.eq_ignore_ascii_case("max-age") {
+                    max_age = Some(value.and_then(delta_seconds));
+                }
+            }
+
+            if let Some(max_age) = max_age {
+                // An invalid age leaves the response stale.
+                let max_age = max_age?;
+                let age = match self.header("age") {
+                    Some(age) => delta_seconds(age)?,
+                    None => 0,
+                };
+
+                let lifetime = Duration::try_seconds(max_age.checked_sub(age)?)?;
+                return self.date().unwrap_or_else(Utc::now).checked_add_signed(lifetime);
+            }
+        }
+
+        self.header("expires").and_then(parse_date)
+    }
+
+    /// The time that the response was generated, as reported by `Date`.
+    pub fn date(&self) -> Option<DateTime<Utc>> {
+        self.header("date").and_then(parse_date)
+    }
+
+    /// The entity tag of the resource, as reported by `ETag`.
+    pub fn etag(&self) -> Option<&str> { self.header("etag") }
+
+    /// The media type of the body, as reported by `Content-Type`.
+    pub fn content_type(&self) -> Option<&str> { self.header("content-type") }
+
+    /// The time that the resource was last modified, as reported by `Last-Modified`.
+    pub fn last_modified(&self) -> Option<DateTime<Utc>> {
+        self.header("last-modified").and_then(parse_date)
+    }
+}
+
+fn parse_date(header: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc2822(header).ok().map(|tz| tz.with_timezone(&Utc))
+}
+
+/// The greatest number of seconds that an age may be, as given by RFC 7234 §1.2.1.
+const MAX_DELTA_SECONDS: i64 = 1 << 31;
+
+/// Parses a non-negative number of seconds, which is clamped to `MAX_DELTA_SECONDS`.
+fn delta_seconds(value: &str) -> Option<i64> {
+    if value.is_empty() || !value.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+
+    let seconds = value.parse::<i64>().unwrap_or(MAX_DELTA_SECONDS);
+    Some(seconds.min(MAX_DELTA_SECONDS))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DATE: &str = "Sun, 18 Oct 2026 12:00:00 GMT";
+
+    fn head(cache_control: &str, age: Option<&str>) -> ResponseHead {
+        let mut headers = vec![
+            ("date".into(), DATE.into()),
+            ("cache-control".into(), cache_control.into()),
+        ];
+
+        if let Some(age) = age {
+            headers.push(("age".into(), age.into()));
+        }
+
+        ResponseHead { status: StatusCode::OK, headers: headers.into() }
+    }
+
+    #[test]
+    fn expires() {
+        let date = parse_date(DATE).unwrap();
+        let expires = |cache_control, age| head(cache_control, age).expires();
+
+        assert_eq!(expires("max-age=60", None), Some(date + Duration::seconds(60)));
+        assert_eq!(expires("max-age=60", Some("20")), Some(date + Duration::seconds(40)));
+        assert_eq!(expires("no-cache, max-age=60", None), None);
+
+        let max = date + Duration::seconds(MAX_DELTA_SECONDS);
+        assert_eq!(expires("max-age=99999999999999", None), Some(max));
+        assert_eq!(expires("max-age=99999999999999999999999", None), Some(max));
+        assert_eq!(
+            expires("max-age=60", Some("99999999999999999999999")),
+            Some(date + Duration::seconds(60 - MAX_DELTA_SECONDS))
+        );
+
+        assert_eq!(expires("max-age=-60", None), None);
+        assert_eq!(expires("max-age=60", Some("-60")), None);
+        assert_eq!(expires("max-age", None), None);
+    }
+}
@@ -0,0 +1,75 @@
+//! Checks that filesystems can hold the files being fetched before fetching them.
+
+use crate::Error;
+use std::{
+    collections::HashMap,
+    io,
+    path::{Path, PathBuf},
+};
+
+/// Identifies a filesystem: by its device on Unix, and elsewhere by its directory.
+#[cfg(unix)]
+type Filesystem = u64;
+
+#[cfg(not(unix))]
+type Filesystem = PathBuf;
+
+/// Totals the space that files will occupy on each filesystem they are written to.
+///
+/// Directories residing on the same filesystem share a single total, so that a batch
+/// of files may be checked against the space available before any are fetched. Where
+/// devices cannot be told apart, which is anywhere but Unix, each directory has its own.
+#[derive(Debug, Default)]
+pub struct SpaceRequirements {
+    devices: HashMap<Filesystem, (PathBuf, u64)>,
+}
+
+impl SpaceRequirements {
+    /// Requires that `bytes` be available to the filesystem holding `dir`.
+    pub fn add(&mut self, dir: &Path, bytes: u64) -> Result<(), Error> {
+        self.entry(dir)?.1 += bytes;
+        Ok(())
+    }
+
+    /// Requires that at least `bytes` be available to the filesystem holding `dir`,
+    /// without adding to what is already required of it.
+    pub fn at_least(&mut self, dir: &Path, bytes: u64) -> Result<(), Error> {
+        let entry = self.entry(dir)?;
+        entry.1 = entry.1.max(bytes);
+        Ok(())
+    }
+
+    fn entry(&mut self, dir: &Path) -> Result<&mut (PathBuf, u64), Error> {
+        let device = filesystem(dir).map_err(Error::SpaceQuery)?;
+        Ok(self.devices.entry(device).or_insert_with(|| (dir.to_owned(), 0)))
+    }
+
+    /// Fails if any filesystem lacks the space which has been required of it.
+    pub fn check(&self) -> Result<(), Error> {
+        for (dir, needed) in self.devices.values() {
+            let available = fs2::available_space(dir).map_err(Error::SpaceQuery)?;
+            if available < *needed {
+                return Err(Error::InsufficientSpace { needed: *needed, available });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+fn filesystem(dir: &Path) -> io::Result<Filesystem> {
+    use std::os::unix::fs::MetadataExt;
+    std::fs::metadata(dir).map(|metadata| metadata.dev())
+}
+
+#[cfg(not(unix))]
+fn filesystem(dir: &Path) -> io::Result<Filesystem> { dir.canonicalize() }
+
+/// The directory which holds a file, treating a bare file name as the current directory.
+pub(crate) fn directory_of(path: &Path) -> &Path {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    }
+}
@@ -0,0 +1,307 @@
+use super::{ChecksumSystem, ChecksummerError, FetcherSystem};
+use crate::{
+    checksum::Checksum, hidden_sibling, partial_path, record::Record, space, sync,
+    Durability, Error, FetchEvent, FetchOutcome, Fetcher, Source,
+};
+use async_std::fs;
+use futures::stream::{self, StreamExt};
+use std::{collections::HashSet, io, num::NonZeroU16, path::Path, sync::Arc};
+use surf::middleware::HttpClient;
+
+#[derive(Debug, Error)]
+pub enum BatchError {
+    #[error("unable to fetch {:?}", _0)]
+    Fetch(Arc<Path>, #[source] Error),
+    #[error("{:?} failed verification", _0)]
+    Checksum(Arc<Path>, #[source] ChecksummerError),
+    #[error("unable to replace {:?}, so every file was restored", _0)]
+    Commit(Arc<Path>, #[source] io::Error),
+}
+
+/// Fetches a batch of sources as a transaction, replacing none of their destinations
+/// unless every one of them was fetched and verified.
+///
+/// Each source is fetched to a hidden sibling of its destination, though its events are
+/// reported under the destination. An existing destination is linked there beforehand,
+/// along with its record, so that it may be found to be up to date rather than fetched
+/// anew. A partial location given by the source is kept.
+///
+/// Once every file is staged and verified, each destination is moved aside and replaced
+/// by its staged file. Should any of those renames fail, every destination is restored.
+#[derive(new, Setters)]
+pub struct BatchSystem<C: HttpClient> {
+    #[setters(skip)]
+    client: Arc<Fetcher<C>>,
+
+    /// The number of sources to fetch, or verify, at a time.
+    #[new(value = "unsafe { NonZeroU16::new_unchecked(4) }")]
+    concurrent: NonZeroU16,
+}
+
+/// A source which is fetched into staging.
+struct Staged {
+    dest:     Arc<Path>,
+    staged:   Arc<Path>,
+    part:     Option<Arc<Path>>,
+    backup:   Arc<Path>,
+    checksum: Option<Arc<Checksum>>,
+    outcome:  Option<FetchOutcome>,
+}
+
+impl<C: HttpClient> BatchSystem<C> {
+    /// Fetches and verifies every source, and then replaces each of their destinations.
+    ///
+    /// Returns the outcome of each source, in the order that they were given. On failure,
+    /// every staged file is removed, and the destinations are left as they were.
+    ///
+    /// The first fetch which fails abandons the rest, and `FetchEvent::Cancelled` is sent
+    /// for each source whose fetch was abandoned, or had yet to begin.
+    pub async fn fetch(
+        self,
+        sources: Vec<Source>,
+    ) -> Result<Vec<(Arc<Path>, FetchOutcome)>, BatchError> {
+        let concurrent = self.concurrent.get() as usize;
+        let mut batch = Vec::with_capacity(sources.len());
+        let mut inputs = Vec::with_capacity(sources.len());
+
+        for (index, mut source) in sources.into_iter().enumerate() {
+            let siblings = hidden_sibling(&source.dest, "staged")
+                .and_then(|staged| Ok((staged, hidden_sibling(&source.dest, "backup")?)));
+
+            let (staged, aside) = match siblings {
+                Ok(siblings) => siblings,
+                Err(why) => {
+                    discard(&batch).await;
+                    return Err(BatchError::Fetch(source.dest, why));
+                }
+            };
+
+            self.seed(&source.dest, &staged, source.part.as_ref()).await;
+
+            batch.push(Staged {
+                dest:     source.dest.clone(),
+                staged:   staged.clone(),
+                part:     source.part.clone(),
+                backup:   aside,
+                checksum: source.checksum.clone(),
+                outcome:  None,
+            });
+
+            source.dest = staged;
+            inputs.push((source, index));
+        }
+
+        let _aliases = Aliases::new(&self.client, &batch);
+
+        // The first failure abandons the fetches which remain.
+        let mut fetches = FetcherSystem::new(self.client.clone())
+            .build(stream::iter(inputs))
+            .buffer_unordered(concurrent);
+
+        while let Some((staged, result)) = fetches.next().await {
+            match result {
+                Ok((outcome, index)) => batch[index].outcome = Some(outcome),
+                Err(why) => {
+                    drop(fetches);
+
+                    let abandoned = batch.iter().filter(|file| {
+                        file.outcome.is_none() && file.staged.as_ref() != staged.as_ref()
+                    });
+
+                    for file in abandoned {
+                        self.client
+                            .send((file.dest.clone(), FetchEvent::Cancelled))
+                            .await;
+                    }
+
+                    discard(&batch).await;
+                    return Err(BatchError::Fetch(dest_of(&batch, &staged), why));
+                }
+            }
+        }
+
+        drop(fetches);
+
+        let checksums = batch.iter().filter_map(|file| {
+            let checksum = file.checksum.as_deref()?;
+            Some((file.staged.clone(), checksum.clone()))
+        });
+
+        let mut verified = ChecksumSystem::new()
+            .build(stream::iter(checksums))
+            .buffer_unordered(concurrent);
+
+        while let Some((staged, result)) = verified.next().await {
+            if let Err(why) = result {
+                drop(verified);
+                discard(&batch).await;
+                return Err(BatchError::Checksum(dest_of(&batch, &staged), why));
+            }
+        }
+
+        drop(verified);
+
+        self.commit(&batch).await?;
+
+        Ok(batch
+            .into_iter()
+            .map(|file| (file.dest, file.outcome.expect("every source was fetched")))
+            .collect())
+    }
+
+    /// Links an existing destination, and its record, to its staging path.
+    async fn seed(&self, dest: &Arc<Path>, staged: &Arc<Path>, part: Option<&Arc<Path>>) {
+        discard_file(staged, part).await;
+
+        if !dest.exists() || fs::hard_link(dest.as_ref(), staged.as_ref()).await.is_err()
+        {
+            return;
+        }
+
+        if let (true, Some(record)) = (self.client.records, Record::load(dest).await) {
+            if let Err(why) = record.save(staged).await {
+                warn!("unable to stage the record of {:?}: {}", dest, why);
+            }
+        }
+    }
+
+    /// Replaces each destination with its staged file, restoring every destination if
+    /// any of them cannot be replaced.
+    async fn commit(&self, batch: &[Staged]) -> Result<(), BatchError> {
+        let mut replaced: Vec<(&Staged, Option<Arc<Path>>)> = Vec::new();
+
+        for file in batch {
+            match replace(file).await {
+                Ok(backup) => replaced.push((file, backup)),
+                Err(why) => {
+                    for (file, backup) in replaced.into_iter().rev() {
+                        restore(file, backup).await;
+                    }
+
+                    discard(batch).await;
+                    return Err(BatchError::Commit(file.dest.clone(), why));
+                }
+            }
+        }
+
+        let mut directories = HashSet::new();
+
+        for (file, backup) in replaced {
+            if let Some(backup) = backup {
+                let _ = fs::remove_file(backup.as_ref()).await;
+            }
+
+            if self.client.records {
+                if let Some(record) = Record::load(&file.staged).await {
+                    if let Err(why) = record.save(&file.dest).await {
+                        error!("unable to record {:?}: {}", file.dest, why);
+                    }
+                }
+            }
+
+            Record::remove(&file.staged).await;
+            directories.insert(space::directory_of(&file.dest));
+        }
+
+        if self.client.durability == Durability::Durable {
+            for directory in directories {
+                if let Err(why) = sync(&Arc::from(directory)).await {
+                    error!("{}", why);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Reports the events of each staged file under its destination, until it is dropped.
+struct Aliases<'a, C: HttpClient> {
+    client: &'a Fetcher<C>,
+    staged: Vec<Arc<Path>>,
+}
+
+impl<'a, C: HttpClient> Aliases<'a, C> {
+    fn new(client: &'a Fetcher<C>, batch: &[Staged]) -> Self {
+        let mut aliases = client.aliases.lock().expect("aliases are poisoned");
+        for file in batch {
+            aliases.insert(file.staged.clone(), file.dest.clone());
+        }
+
+        Self { client, staged: batch.iter().map(|file| file.staged.clone()).collect() }
+    }
+}
+
+impl<C: HttpClient> Drop for Aliases<'_, C> {
+    fn drop(&mut self) {
+        let mut aliases = self.client.aliases.lock().expect("aliases are poisoned");
+        for staged in &self.staged {
+            aliases.remove(staged);
+        }
+    }
+}
+
+/// Moves a destination aside, and renames its staged file over it.
+///
+/// Returns where the destination was moved to, if it existed.
+async fn replace(file: &Staged) -> io::Result<Option<Arc<Path>>> {
+    let backup = if file.dest.exists() {
+        fs::rename(file.dest.as_ref(), file.backup.as_ref()).await?;
+        Some(file.backup.clone())
+    } else {
+        None
+    };
+
+    if let Err(why) = fs::rename(file.staged.as_ref(), file.dest.as_ref()).await {
+        if let Some(backup) = backup {
+            let _ = fs::rename(backup.as_ref(), file.dest.as_ref()).await;
+        }
+
+        return Err(why);
+    }
+
+    Ok(backup)
+}
+
+/// Returns a destination which was replaced to how it was before.
+async fn restore(file: &Staged, backup: Option<Arc<Path>>) {
+    let result = match backup {
+        Some(backup) => fs::rename(backup.as_ref(), file.dest.as_ref()).await,
+        None => fs::remove_file(file.dest.as_ref()).await,
+    };
+
+    if let Err(why) = result {
+        error!("unable to restore {:?}: {}", file.dest, why);
+    }
+}
+
+/// Removes every file which was staged for the batch.
+async fn discard(batch: &[Staged]) {
+    for file in batch {
+        discard_file(&file.staged, file.part.as_ref()).await;
+    }
+}
+
+/// Removes a staged file, along with its record and any partial file.
+async fn discard_file(staged: &Arc<Path>, part: Option<&Arc<Path>>) {
+    let _ = fs::remove_file(staged.as_ref()).await;
+    Record::remove(staged).await;
+
+    let partial = match part {
+        Some(part) => Ok(part.clone()),
+        None => partial_path(staged),
+    };
+
+    if let Ok(partial) = partial {
+        let _ = fs::remove_file(partial.as_ref()).await;
+        Record::remove(&partial).await;
+    }
+}
+
+/// The destination of a file which was staged at the given path.
+fn dest_of(batch: &[Staged], staged: &Path) -> Arc<Path> {
+    batch
+        .iter()
+        .find(|file| file.staged.as_ref() == staged)
+        .map_or_else(|| Arc::from(staged), |file| file.dest.clone())
+}
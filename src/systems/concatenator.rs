@@ -1,19 +1,25 @@
-use crate::Error;
+use crate::{checksum::Hasher, Error};
 
 use async_std::{
     fs::{self, File},
-    io::copy,
     prelude::*,
 };
 use std::{path::Path, sync::Arc};
 
-pub async fn concatenator<P>(dest: &mut File, mut parts: P) -> Result<(), Error>
+/// Appends each part to the destination in order, feeding their bytes to the hasher.
+pub async fn concatenator<P>(
+    dest: &mut File,
+    mut parts: P,
+    mut hasher: Option<&mut Hasher>,
+) -> Result<(), Error>
 where
     P: Stream<Item = Result<Arc<Path>, Error>> + Unpin,
 {
+    let buffer = &mut [0u8; 8 * 1024];
+
     while let Some(task_result) = parts.next().await {
         let part_path: Arc<Path> = task_result?;
-        concatenate(dest, part_path).await?;
+        concatenate(dest, part_path, buffer, hasher.as_deref_mut()).await?;
     }
 
     Ok(())
@@ -22,12 +28,26 @@ where
 async fn concatenate(
     concatenated_file: &mut File,
     part_path: Arc<Path>,
+    buffer: &mut [u8],
+    mut hasher: Option<&mut Hasher>,
 ) -> Result<(), Error> {
     let mut file = File::open(&*part_path)
         .await
         .map_err(|why| Error::OpenPart(part_path.clone(), why))?;
 
-    copy(&mut file, concatenated_file).await.map_err(Error::Concatenate)?;
+    loop {
+        let read = file.read(buffer).await.map_err(Error::Concatenate)?;
+
+        if read == 0 {
+            break;
+        }
+
+        if let Some(hasher) = hasher.as_mut() {
+            hasher.input(&buffer[..read]);
+        }
+
+        concatenated_file.write_all(&buffer[..read]).await.map_err(Error::Concatenate)?;
+    }
 
     if let Err(why) = fs::remove_file(&*part_path).await {
         error!("failed to remove part file ({:?}): {}", part_path, why);
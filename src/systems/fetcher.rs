@@ -1,6 +1,5 @@
-use crate::{Error, FetchEvent, Fetcher, Source};
+use crate::{Error, FetchOutcome, Fetcher, Source};
 
-use async_std::fs;
 use futures::prelude::*;
 use std::{path::Path, sync::Arc};
 use surf::middleware::HttpClient;
@@ -15,7 +14,7 @@ impl<C: HttpClient> FetcherSystem<C> {
     pub fn build<I, T>(
         self,
         inputs: I,
-    ) -> impl Stream<Item = impl Future<Output = (Arc<Path>, Result<T, Error>)>>
+    ) -> impl Stream<Item = impl Future<Output = (Arc<Path>, Result<(FetchOutcome, T), Error>)>>
     where
         I: Stream<Item = (Source, T)> + Unpin + Send + 'static,
     {
@@ -23,25 +22,8 @@ impl<C: HttpClient> FetcherSystem<C> {
             let fetcher = self.client.clone();
 
             async move {
-                let Source { dest, urls, part, .. } = source;
-
-                fetcher.send((dest.clone(), FetchEvent::Fetching));
-
-                let result = match part {
-                    Some(part) => {
-                        match fetcher.clone().request(urls, part.clone()).await {
-                            Ok(()) => {
-                                fs::rename(&*part, &*dest).await.map_err(Error::Rename)
-                            }
-                            Err(why) => Err(why),
-                        }
-                    }
-                    None => fetcher.clone().request(urls, dest.clone()).await,
-                };
-
-                fetcher.send((dest.clone(), FetchEvent::Fetched));
-
-                (dest, result.map(|_| extra))
+                let result = fetcher.fetch(&source).await;
+                (source.dest, result.map(|outcome| (outcome, extra)))
             }
         })
     }
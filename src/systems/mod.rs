@@ -1,5 +1,11 @@
+mod batch;
 mod checksum;
 mod concatenator;
 mod fetcher;
 
-pub use self::{checksum::*, concatenator::concatenator, fetcher::FetcherSystem};
+pub use self::{
+    batch::{BatchError, BatchSystem},
+    checksum::*,
+    concatenator::concatenator,
+    fetcher::FetcherSystem,
+};
@@ -0,0 +1,279 @@
+//! Fetches of resources served by the mock transport.
+
+use crate::{
+    checksum::{Checksum, Pieces},
+    events::{Callback, Coalesce, EventSink},
+    progress::ProgressTracker,
+    transport::mock::{MockTransport, Resource},
+    BatchEvent, Error, FetchEvent, FetchOutcome, FetchStatus, Fetcher, Source,
+};
+use futures::{
+    channel::mpsc,
+    future::{self, FutureExt, LocalBoxFuture},
+    StreamExt,
+};
+use http::StatusCode;
+use http_client::native::NativeClient;
+use sha2::{Digest, Sha256};
+use std::{
+    num::{NonZeroU16, NonZeroU64},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+const BODY: &[u8] = b"The quick brown fox jumps over the lazy dog";
+
+/// An empty directory which is unique to the test.
+fn directory(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "async-fetcher-{}-{}",
+        test,
+        std::process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn fetcher(mock: &MockTransport) -> Fetcher<NativeClient> {
+    Fetcher::default().with_transport("mock", mock.clone())
+}
+
+fn source(urls: &[&str], dest: &Path) -> Source {
+    let urls = urls.iter().map(|&url| Box::from(url)).collect::<Vec<_>>();
+    Source::new(urls, dest)
+}
+
+fn sha256(data: &[u8]) -> Checksum { Checksum::Sha256(Sha256::digest(data)) }
+
+fn corrupt(data: &[u8], at: usize) -> Vec<u8> {
+    let mut data = data.to_vec();
+    data[at] ^= 0xFF;
+    data
+}
+
+fn fetch(fetcher: Fetcher<NativeClient>, source: &Source) -> Result<FetchOutcome, Error> {
+    async_std::task::block_on(fetcher.into_arc().fetch(source))
+}
+
+#[test]
+fn rejects_a_truncated_body() {
+    let dir = directory("truncated");
+    let mock = MockTransport::default().serve(
+        "mock://a/file",
+        Resource { length: Some(BODY.len() as u64 + 1), ..Resource::new(BODY) },
+    );
+
+    let fetcher = fetcher(&mock).retries(NonZeroU16::new(1).unwrap());
+    let error =
+        fetch(fetcher, &source(&["mock://a/file"], &dir.join("file"))).unwrap_err();
+
+    let expected = BODY.len() as u64 + 1;
+    let received = BODY.len() as u64;
+    assert!(matches!(
+        error.inner(),
+        Error::UnexpectedLength { expected: e, received: r } if *e == expected && *r == received
+    ));
+    assert!(!dir.join("file").exists());
+}
+
+#[test]
+fn rejects_a_mismatched_content_range() {
+    let dir = directory("content-range");
+    let mock = MockTransport::default().serve(
+        "mock://a/file",
+        Resource { content_range: Some("bytes 1-10/43".into()), ..Resource::new(BODY) },
+    );
+
+    let fetcher = fetcher(&mock)
+        .retries(NonZeroU16::new(1).unwrap())
+        .connections_per_file(NonZeroU16::new(2));
+
+    let error =
+        fetch(fetcher, &source(&["mock://a/file"], &dir.join("file"))).unwrap_err();
+
+    assert!(matches!(
+        error.inner(),
+        Error::ContentRange { requested: (0, _), received: Some(received) }
+            if received.as_ref() == "bytes 1-10/43"
+    ));
+}
+
+#[test]
+fn retries_a_file_which_fails_its_checksum() {
+    let dir = directory("checksum");
+    let mock = MockTransport::default()
+        .serve("mock://a/file", Resource::new(corrupt(BODY, 3)).then(BODY));
+
+    let source =
+        source(&["mock://a/file"], &dir.join("file")).checksum(Arc::new(sha256(BODY)));
+    let outcome = fetch(fetcher(&mock), &source).unwrap();
+
+    assert_eq!(outcome.status, FetchStatus::Downloaded);
+    assert_eq!(outcome.retries, 1);
+    assert_eq!(std::fs::read(dir.join("file")).unwrap(), BODY);
+}
+
+#[test]
+fn refetches_a_corrupted_piece_from_the_next_mirror() {
+    let dir = directory("pieces");
+    let size = NonZeroU64::new(16).unwrap();
+    let checksums = BODY.chunks(16).map(sha256).collect::<Vec<_>>();
+
+    // The first mirror corrupts the second piece, which is first requested from it.
+    let mock = MockTransport::default()
+        .serve("mock://b/file", Resource::new(corrupt(BODY, 20)))
+        .serve("mock://a/file", Resource::new(BODY));
+
+    let source = source(&["mock://a/file", "mock://b/file"], &dir.join("file"))
+        .pieces(Arc::new(Pieces::new(size, checksums)));
+
+    let tracker = Arc::new(ProgressTracker::new(Duration::from_secs(60)));
+    let events = {
+        let tracker = tracker.clone();
+        Callback(move |dest: Arc<Path>, event| {
+            tracker.track(&dest, &event);
+        })
+    };
+
+    let outcome = fetch(fetcher(&mock).events(events), &source).unwrap();
+
+    assert_eq!(outcome.retries, 0);
+    assert_eq!(std::fs::read(dir.join("file")).unwrap(), BODY);
+
+    // The bytes of the corrupted piece are not counted twice.
+    assert_eq!(tracker.progress().bytes, BODY.len() as u64);
+
+    let reads = mock.reads();
+    let second = reads.iter().filter(|(_, range)| *range == Some((16, 31)));
+    let urls = second.map(|(url, _)| url.as_ref()).collect::<Vec<_>>();
+    assert_eq!(urls, ["mock://b/file", "mock://a/file"]);
+}
+
+#[test]
+fn lists_every_failed_attempt() {
+    let dir = directory("exhausted");
+    let mock = MockTransport::default()
+        .serve("mock://a/file", Resource::failing(StatusCode::SERVICE_UNAVAILABLE))
+        .serve("mock://b/file", Resource::failing(StatusCode::NOT_FOUND));
+
+    let source = source(&["mock://a/file", "mock://b/file"], &dir.join("file"));
+    let error = fetch(fetcher(&mock), &source).unwrap_err();
+
+    let attempts = match &error {
+        Error::Exhausted(attempts) => &attempts.0,
+        other => panic!("expected every attempt to be listed, found {:?}", other),
+    };
+
+    let urls = attempts.iter().map(|attempt| attempt.url.as_ref()).collect::<Vec<_>>();
+    assert_eq!(urls, ["mock://a/file", "mock://b/file", "mock://a/file"]);
+
+    for (number, attempt) in attempts.iter().enumerate() {
+        assert_eq!(
+            attempt.error.context().map(|context| context.attempt),
+            Some(number as u16 + 1)
+        );
+    }
+
+    assert!(matches!(error.inner(), Error::Status(StatusCode::SERVICE_UNAVAILABLE, _)));
+    assert!(error.is_transient());
+}
+
+#[test]
+fn sends_one_terminal_event_per_fetch() {
+    let dir = directory("events");
+    let mock = MockTransport::default()
+        .serve("mock://a/file", Resource::new(BODY))
+        .serve("mock://a/missing", Resource::failing(StatusCode::NOT_FOUND));
+
+    let (sender, receiver) = mpsc::unbounded();
+    let fetcher = fetcher(&mock).events(sender).into_arc();
+
+    let fetched =
+        source(&["mock://a/file"], &dir.join("fetched")).checksum(Arc::new(sha256(BODY)));
+    let failed = source(&["mock://a/missing"], &dir.join("failed"));
+
+    async_std::task::block_on(async {
+        fetcher.clone().fetch(&fetched).await.unwrap();
+        fetcher.clone().fetch(&fetched).await.unwrap();
+        fetcher.clone().fetch(&failed).await.unwrap_err();
+    });
+
+    drop(fetcher);
+
+    let mut terminal = Vec::new();
+    for (dest, event) in async_std::task::block_on(receiver.collect::<Vec<_>>()) {
+        let name = match event {
+            FetchEvent::Fetching => "fetching",
+            FetchEvent::Fetched => "fetched",
+            FetchEvent::AlreadyFetched => "already fetched",
+            FetchEvent::Failed(_) => "failed",
+            FetchEvent::Cancelled => "cancelled",
+            _ => continue,
+        };
+
+        terminal.push((dest.file_name().unwrap().to_string_lossy().into_owned(), name));
+    }
+
+    let expected = [
+        ("fetched", "fetching"),
+        ("fetched", "fetched"),
+        ("fetched", "fetching"),
+        ("fetched", "already fetched"),
+        ("failed", "fetching"),
+        ("failed", "failed"),
+    ];
+
+    let terminal =
+        terminal.iter().map(|(dest, name)| (dest.as_str(), *name)).collect::<Vec<_>>();
+    assert_eq!(terminal, expected);
+}
+
+/// Records the events of the batch, and the destination of every other event.
+#[derive(Clone, Default)]
+struct Recorder {
+    batch: Arc<Mutex<Vec<BatchEvent>>>,
+    dests: Arc<Mutex<Vec<Arc<Path>>>>,
+}
+
+impl EventSink for Recorder {
+    fn send(&self, (dest, _): (Arc<Path>, FetchEvent)) -> LocalBoxFuture<'_, ()> {
+        self.dests.lock().unwrap().push(dest);
+        future::ready(()).boxed_local()
+    }
+
+    fn send_batch(&self, event: BatchEvent) -> LocalBoxFuture<'_, ()> {
+        self.batch.lock().unwrap().push(event);
+        future::ready(()).boxed_local()
+    }
+}
+
+#[test]
+fn sends_the_batch_size_apart_from_file_events() {
+    let dir = directory("preflight");
+    let mock = MockTransport::default()
+        .serve("mock://a/file", Resource::new(BODY))
+        .serve("mock://a/missing", Resource::failing(StatusCode::NOT_FOUND));
+
+    let recorder = Recorder::default();
+    let fetcher = fetcher(&mock)
+        .events(Coalesce::new(recorder.clone(), Duration::from_secs(1)))
+        .into_arc();
+
+    let sources = [
+        source(&["mock://a/file"], &dir.join("file")),
+        source(&["mock://a/missing"], &dir.join("missing")),
+    ];
+
+    let concurrent = NonZeroU16::new(2).unwrap();
+    let report = async_std::task::block_on(fetcher.preflight(&sources, concurrent));
+    assert_eq!((report.stale, report.failed), (1, 1));
+
+    let batch = recorder.batch.lock().unwrap();
+    assert!(matches!(
+        batch[..],
+        [BatchEvent::Size { files: 1, bytes }] if bytes == BODY.len() as u64
+    ));
+    assert!(recorder.dests.lock().unwrap().iter().all(|dest| dest.starts_with(&dir)));
+}
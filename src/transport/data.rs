@@ -0,0 +1,131 @@
+use super::{ReadRequest, Response, Transport};
+use crate::{Error, ResponseHead};
+use futures::{
+    future::{FutureExt, LocalBoxFuture},
+    io::Cursor,
+};
+use http::StatusCode;
+use percent_encoding::percent_decode_str;
+
+/// Serves the contents embedded within `data:` URLs, as described by RFC 2397.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DataTransport;
+
+impl DataTransport {
+    fn decode(url: &str) -> Result<(ResponseHead, Vec<u8>), Error> {
+        let invalid = || Error::InvalidData(url.into());
+
+        let (meta, data) = url
+            .strip_prefix("data:")
+            .and_then(|rest| {
+                let pos = rest.find(',')?;
+                Some((&rest[..pos], &rest[pos + 1..]))
+            })
+            .ok_or_else(invalid)?;
+
+        let (media_type, base64) = match meta.strip_suffix(";base64") {
+            Some(media_type) => (media_type, true),
+            None => (meta, false),
+        };
+
+        let data = percent_decode_str(data).collect::<Vec<u8>>();
+        let data =
+            if base64 { base64::decode(&data).map_err(|_| invalid())? } else { data };
+
+        let media_type = if media_type.is_empty() { "text/plain" } else { media_type };
+
+        let head = ResponseHead {
+            status:  StatusCode::OK,
+            headers: vec![
+                ("content-length".into(), data.len().to_string().into()),
+                ("content-type".into(), media_type.into()),
+            ]
+            .into(),
+        };
+
+        Ok((head, data))
+    }
+}
+
+impl Transport for DataTransport {
+    fn probe<'a>(
+        &'a self,
+        url: &'a str,
+    ) -> LocalBoxFuture<'a, Result<Option<ResponseHead>, Error>> {
+        async move { Self::decode(url).map(|(head, _)| Some(head)) }.boxed_local()
+    }
+
+    fn supports_range<'a>(
+        &'a self,
+        _url: &'a str,
+        _length: u64,
+    ) -> LocalBoxFuture<'a, Result<bool, Error>> {
+        async { Ok(true) }.boxed_local()
+    }
+
+    fn read<'a>(
+        &'a self,
+        request: ReadRequest<'a>,
+    ) -> LocalBoxFuture<'a, Result<Response, Error>> {
+        async move {
+            let (mut head, mut data) = Self::decode(request.url)?;
+
+            if let Some((start, end)) = request.range {
+                let length = data.len() as u64;
+                if start >= length {
+                    let unsatisfiable = format!("bytes */{}", length).into();
+                    return Err(Error::Status(
+                        StatusCode::RANGE_NOT_SATISFIABLE,
+                        unsatisfiable,
+                    ));
+                }
+
+                let end = end.min(length - 1);
+                data = data[start as usize..=end as usize].to_vec();
+
+                head.status = StatusCode::PARTIAL_CONTENT;
+                head.headers = vec![
+                    ("content-length".into(), data.len().to_string().into()),
+                    (
+                        "content-range".into(),
+                        format!("bytes {}-{}/{}", start, end, length).into(),
+                    ),
+                ]
+                .into();
+            }
+
+            Ok(Response { head, body: Box::new(Cursor::new(data)) })
+        }
+        .boxed_local()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::io::AsyncReadExt;
+
+    fn read(url: &str, range: (u64, u64)) -> Result<(ResponseHead, Vec<u8>), Error> {
+        async_std::task::block_on(async {
+            let mut response =
+                DataTransport.read(ReadRequest::new(url).range(range)).await?;
+            let mut body = Vec::new();
+            response.body.read_to_end(&mut body).await.unwrap();
+            Ok((response.head, body))
+        })
+    }
+
+    #[test]
+    fn reads_ranges() {
+        let (head, body) = read("data:,abcd", (1, 10)).unwrap();
+        assert_eq!(head.status, StatusCode::PARTIAL_CONTENT);
+        assert_eq!(head.header("content-range"), Some("bytes 1-3/4"));
+        assert_eq!(body, b"bcd");
+
+        let error = read("data:,abcd", (10, 12)).unwrap_err();
+        assert!(matches!(
+            error,
+            Error::Status(StatusCode::RANGE_NOT_SATISFIABLE, ref range) if &**range == "bytes */4"
+        ));
+    }
+}
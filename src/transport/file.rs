@@ -0,0 +1,153 @@
+use super::{ReadRequest, Response, Transport};
+use crate::{Error, ResponseHead};
+use async_std::{fs::File, io::SeekFrom, prelude::*};
+use chrono::{DateTime, Utc};
+use futures::{
+    future::{FutureExt, LocalBoxFuture},
+    io::empty,
+};
+use http::StatusCode;
+use percent_encoding::percent_decode_str;
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// Serves `file:` URLs and plain paths from the local filesystem.
+///
+/// This permits a local mirror, network mount, or removable drive to be listed next to
+/// remote mirrors, with the same freshness checks applied to it.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FileTransport;
+
+impl FileTransport {
+    async fn head(path: &Arc<Path>) -> Result<(ResponseHead, DateTime<Utc>), Error> {
+        let metadata =
+            async_std::fs::metadata(&**path).await.map_err(|why| local(path, why))?;
+        let modified =
+            DateTime::<Utc>::from(metadata.modified().map_err(|why| local(path, why))?);
+
+        let head = ResponseHead {
+            status:  StatusCode::OK,
+            headers: vec![
+                ("content-length".into(), metadata.len().to_string().into()),
+                ("last-modified".into(), modified.to_rfc2822().into()),
+            ]
+            .into(),
+        };
+
+        Ok((head, modified))
+    }
+}
+
+impl Transport for FileTransport {
+    fn probe<'a>(
+        &'a self,
+        url: &'a str,
+    ) -> LocalBoxFuture<'a, Result<Option<ResponseHead>, Error>> {
+        async move { Self::head(&path(url)?).await.map(|(head, _)| Some(head)) }
+            .boxed_local()
+    }
+
+    fn supports_range<'a>(
+        &'a self,
+        _url: &'a str,
+        _length: u64,
+    ) -> LocalBoxFuture<'a, Result<bool, Error>> {
+        async { Ok(true) }.boxed_local()
+    }
+
+    fn read<'a>(
+        &'a self,
+        request: ReadRequest<'a>,
+    ) -> LocalBoxFuture<'a, Result<Response, Error>> {
+        async move {
+            let path = path(request.url)?;
+            let (mut head, modified) = Self::head(&path).await?;
+
+            if let Some(since) = request.if_modified_since {
+                if modified.timestamp() <= since.timestamp() {
+                    head.status = StatusCode::NOT_MODIFIED;
+                    return Ok(Response { head, body: Box::new(empty()) });
+                }
+            }
+
+            let mut file = File::open(&*path).await.map_err(|why| local(&path, why))?;
+
+            let body: super::Body = match request.range {
+                Some((start, end)) => {
+                    let length = head.content_length().unwrap_or(0);
+                    if start >= length {
+                        let unsatisfiable = format!("bytes */{}", length).into();
+                        return Err(Error::Status(
+                            StatusCode::RANGE_NOT_SATISFIABLE,
+                            unsatisfiable,
+                        ));
+                    }
+
+                    let end = end.min(length - 1);
+
+                    file.seek(SeekFrom::Start(start))
+                        .await
+                        .map_err(|why| local(&path, why))?;
+
+                    head.status = StatusCode::PARTIAL_CONTENT;
+                    head.headers = vec![
+                        ("content-length".into(), (end + 1 - start).to_string().into()),
+                        (
+                            "content-range".into(),
+                            format!("bytes {}-{}/{}", start, end, length).into(),
+                        ),
+                        ("last-modified".into(), modified.to_rfc2822().into()),
+                    ]
+                    .into();
+
+                    Box::new(file.take(end + 1 - start))
+                }
+                None => Box::new(file),
+            };
+
+            Ok(Response { head, body })
+        }
+        .boxed_local()
+    }
+}
+
+fn local(path: &Arc<Path>, why: io::Error) -> Error { Error::Local(path.clone(), why) }
+
+/// A path from the bytes of a decoded URL, which are only known to be UTF-8 off Unix.
+#[cfg(unix)]
+fn from_bytes(bytes: Vec<u8>) -> PathBuf {
+    use std::{ffi::OsString, os::unix::ffi::OsStringExt};
+    PathBuf::from(OsString::from_vec(bytes))
+}
+
+#[cfg(not(unix))]
+fn from_bytes(bytes: Vec<u8>) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// Interprets a `file:` URL or a plain path as a location on the local filesystem.
+fn path(uri: &str) -> Result<Arc<Path>, Error> {
+    let path = match uri.strip_prefix("file:") {
+        Some(path) => path,
+        None => return Ok(Arc::from(Path::new(uri))),
+    };
+
+    // Only the local host may be named by a file URL.
+    let path = match path.strip_prefix("//") {
+        Some(path) => path.strip_prefix("localhost").unwrap_or(path),
+        None => path,
+    };
+
+    let decoded = percent_decode_str(path).collect::<Vec<u8>>();
+    let path = Arc::<Path>::from(from_bytes(decoded));
+
+    if path.is_absolute() {
+        Ok(path)
+    } else {
+        let why = io::Error::new(io::ErrorKind::InvalidInput, "not a local file URL");
+        Err(local(&path, why))
+    }
+}
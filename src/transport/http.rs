@@ -0,0 +1,90 @@
+use super::{ReadRequest, Response, Transport};
+use crate::{range, Error, ResponseHead};
+use futures::{
+    future::{FutureExt, LocalBoxFuture},
+    io::AsyncReadExt,
+};
+use http::StatusCode;
+use surf::{middleware::HttpClient, Client};
+
+impl<C: HttpClient> Transport for Client<C> {
+    fn probe<'a>(
+        &'a self,
+        url: &'a str,
+    ) -> LocalBoxFuture<'a, Result<Option<ResponseHead>, Error>> {
+        async move {
+            match validate(self.head(url).set_header("Expect", "").await?).await {
+                Ok(mut response) => Ok(Some(ResponseHead::from_response(&mut response))),
+                Err(Error::Status(StatusCode::NOT_IMPLEMENTED, _)) => Ok(None),
+                Err(other) => Err(other),
+            }
+        }
+        .boxed_local()
+    }
+
+    fn supports_range<'a>(
+        &'a self,
+        url: &'a str,
+        length: u64,
+    ) -> LocalBoxFuture<'a, Result<bool, Error>> {
+        async move {
+            let response = self
+                .head(url)
+                .set_header("Expect", "")
+                .set_header("range", range::to_string(0, length).as_str())
+                .await?;
+
+            if response.status() == StatusCode::PARTIAL_CONTENT {
+                Ok(true)
+            } else {
+                validate(response).await.map(|_| false)
+            }
+        }
+        .boxed_local()
+    }
+
+    fn read<'a>(
+        &'a self,
+        request: ReadRequest<'a>,
+    ) -> LocalBoxFuture<'a, Result<Response, Error>> {
+        async move {
+            let mut get = self.get(request.url).set_header("Expect", "");
+
+            if let Some((start, end)) = request.range {
+                get = get.set_header("range", range::to_string(start, end).as_str());
+            }
+
+            if let Some(since) = request.if_modified_since {
+                get = get.set_header("if-modified-since", since.to_rfc2822().as_str());
+            }
+
+            if let Some(etag) = request.if_none_match {
+                get = get.set_header("if-none-match", etag);
+            }
+
+            let mut response = validate(get.await?).await?;
+            let head = ResponseHead::from_response(&mut response);
+
+            Ok(Response { head, body: Box::new(response) })
+        }
+        .boxed_local()
+    }
+}
+
+/// The number of bytes of an error response's body which are kept in its error.
+const EXCERPT_LENGTH: u64 = 512;
+
+async fn validate(response: surf::Response) -> Result<surf::Response, Error> {
+    let status = response.status();
+
+    if status.as_u16() < 300 || status == StatusCode::NOT_MODIFIED {
+        return Ok(response);
+    }
+
+    // The start of the body often explains the error, such as a proxy's error page.
+    let mut excerpt = Vec::new();
+    let _ = response.take(EXCERPT_LENGTH).read_to_end(&mut excerpt).await;
+    let excerpt = String::from_utf8_lossy(&excerpt);
+
+    Err(Error::Status(status, excerpt.trim().into()))
+}
@@ -0,0 +1,164 @@
+//! A transport which serves fixed responses, so that fetches may be tested offline.
+
+use super::{ReadRequest, Response, Transport};
+use crate::{Error, ResponseHead};
+use futures::{
+    future::{FutureExt, LocalBoxFuture},
+    io::Cursor,
+};
+use http::StatusCode;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+};
+
+/// A resource which the mock transport serves.
+#[derive(Clone, Debug)]
+pub(crate) struct Resource {
+    /// The status of every response, which fails each read when it is an error.
+    pub status: StatusCode,
+
+    /// The body of each successive read, the last of which is served from then on.
+    pub bodies: VecDeque<Vec<u8>>,
+
+    /// The length which is advertised, in place of the length of the body.
+    pub length: Option<u64>,
+
+    /// The `Content-Range` which is sent, in place of the range that was read.
+    pub content_range: Option<Box<str>>,
+
+    /// Whether the resource may be read in ranges.
+    pub ranges: bool,
+}
+
+impl Resource {
+    pub fn new(body: impl Into<Vec<u8>>) -> Self {
+        Self {
+            status:        StatusCode::OK,
+            bodies:        vec![body.into()].into(),
+            length:        None,
+            content_range: None,
+            ranges:        true,
+        }
+    }
+
+    /// A resource which answers every request with the given status.
+    pub fn failing(status: StatusCode) -> Self {
+        Self { status, ..Self::new(Vec::new()) }
+    }
+
+    /// Serves each body in turn, and the last of them once every other is served.
+    pub fn then(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.bodies.push_back(body.into());
+        self
+    }
+
+    fn head(&self, length: u64) -> ResponseHead {
+        let length = self.length.unwrap_or(length);
+        ResponseHead {
+            status:  self.status,
+            headers: vec![("content-length".into(), length.to_string().into())].into(),
+        }
+    }
+
+    fn body(&mut self) -> Vec<u8> {
+        match self.bodies.len() {
+            0 | 1 => self.bodies.front().cloned().unwrap_or_default(),
+            _ => self.bodies.pop_front().unwrap_or_default(),
+        }
+    }
+}
+
+/// The URL and range of a read.
+pub(crate) type Read = (Box<str>, Option<(u64, u64)>);
+
+/// Serves resources from memory, and records every read that is made of them.
+#[derive(Clone, Default)]
+pub(crate) struct MockTransport {
+    resources: Arc<Mutex<HashMap<Box<str>, Resource>>>,
+    reads:     Arc<Mutex<Vec<Read>>>,
+}
+
+impl MockTransport {
+    pub fn serve(self, url: &str, resource: Resource) -> Self {
+        self.resources.lock().unwrap().insert(url.into(), resource);
+        self
+    }
+
+    /// The URL and range of every read which was made, in order.
+    pub fn reads(&self) -> Vec<Read> { self.reads.lock().unwrap().clone() }
+
+    fn resource(&self, url: &str) -> Result<Resource, Error> {
+        let resources = self.resources.lock().unwrap();
+        let resource = resources.get(url).cloned();
+        let resource =
+            resource.unwrap_or_else(|| Resource::failing(StatusCode::NOT_FOUND));
+
+        if resource.status.as_u16() >= 300 {
+            return Err(Error::Status(resource.status, "".into()));
+        }
+
+        Ok(resource)
+    }
+}
+
+impl Transport for MockTransport {
+    fn probe<'a>(
+        &'a self,
+        url: &'a str,
+    ) -> LocalBoxFuture<'a, Result<Option<ResponseHead>, Error>> {
+        async move {
+            let resource = self.resource(url)?;
+            let length = resource.bodies.front().map_or(0, Vec::len) as u64;
+            Ok(Some(resource.head(length)))
+        }
+        .boxed_local()
+    }
+
+    fn supports_range<'a>(
+        &'a self,
+        url: &'a str,
+        _length: u64,
+    ) -> LocalBoxFuture<'a, Result<bool, Error>> {
+        async move { Ok(self.resource(url)?.ranges) }.boxed_local()
+    }
+
+    fn read<'a>(
+        &'a self,
+        request: ReadRequest<'a>,
+    ) -> LocalBoxFuture<'a, Result<Response, Error>> {
+        async move {
+            self.reads.lock().unwrap().push((request.url.into(), request.range));
+            self.resource(request.url)?;
+
+            let (resource, mut body) = {
+                let mut resources = self.resources.lock().unwrap();
+                let resource = resources.get_mut(request.url).expect("resource exists");
+                let body = resource.body();
+                (resource.clone(), body)
+            };
+
+            let mut head = resource.head(body.len() as u64);
+
+            if let Some((start, end)) = request.range {
+                let length = body.len() as u64;
+                let end = end.min(length - 1);
+                body = body[start as usize..=end as usize].to_vec();
+
+                let content_range = format!("bytes {}-{}/{}", start, end, length);
+                let content_range =
+                    resource.content_range.unwrap_or(content_range.into());
+
+                head.status = StatusCode::PARTIAL_CONTENT;
+                head.headers = vec![
+                    ("content-length".into(), body.len().to_string().into()),
+                    ("content-range".into(), content_range),
+                ]
+                .into();
+            }
+
+            Ok(Response { head, body: Box::new(Cursor::new(body)) })
+        }
+        .boxed_local()
+    }
+}
@@ -0,0 +1,118 @@
+//! Backends which serve the resources that the fetcher requests.
+//!
+//! Each URL is dispatched to a transport by its scheme. HTTP and HTTPS are served by
+//! the surf client given to the fetcher, whereas `file:` URLs, plain paths, and `data:`
+//! URLs are served by the built-in [`FileTransport`] and [`DataTransport`]. Additional
+//! backends, or replacements for the built-in ones, may be registered with
+//! `Fetcher::with_transport`.
+
+mod data;
+mod file;
+mod http;
+#[cfg(test)]
+pub(crate) mod mock;
+
+pub use self::{data::DataTransport, file::FileTransport};
+
+use crate::{Error, ResponseHead};
+use chrono::{DateTime, Utc};
+use futures::{future::LocalBoxFuture, io::AsyncRead};
+
+/// The body of a response, which is read as a stream of bytes.
+pub type Body = Box<dyn AsyncRead + Unpin>;
+
+/// Describes how a resource should be read.
+#[derive(Clone, Debug, Setters)]
+pub struct ReadRequest<'a> {
+    /// The URL of the resource.
+    #[setters(skip)]
+    pub url: &'a str,
+
+    /// An inclusive range of bytes to read, rather than the entire resource.
+    #[setters(strip_option)]
+    pub range: Option<(u64, u64)>,
+
+    /// Only read the resource if it was modified after this time.
+    pub if_modified_since: Option<DateTime<Utc>>,
+
+    /// Only read the resource if its entity tag differs from this one.
+    pub if_none_match: Option<&'a str>,
+}
+
+impl<'a> ReadRequest<'a> {
+    pub fn new(url: &'a str) -> Self {
+        Self { url, range: None, if_modified_since: None, if_none_match: None }
+    }
+}
+
+/// A response whose body has yet to be read.
+pub struct Response {
+    pub head: ResponseHead,
+    pub body: Body,
+}
+
+/// The operations which the fetcher requires of a backend.
+///
+/// Responses with a status of 300 or greater must be returned as `Error::Status`, along
+/// with the start of their body, with the exception of `304 Not Modified`, which is
+/// returned as a response with an empty body when a conditional read finds the resource
+/// unchanged.
+pub trait Transport: Send + Sync {
+    /// Fetches the status and headers of a resource without reading its body.
+    ///
+    /// Returns `None` if the backend is unable to describe the resource in advance.
+    fn probe<'a>(
+        &'a self,
+        url: &'a str,
+    ) -> LocalBoxFuture<'a, Result<Option<ResponseHead>, Error>>;
+
+    /// Checks if a resource of the given length can be read in ranges.
+    fn supports_range<'a>(
+        &'a self,
+        url: &'a str,
+        length: u64,
+    ) -> LocalBoxFuture<'a, Result<bool, Error>>;
+
+    /// Begins reading a resource.
+    fn read<'a>(
+        &'a self,
+        request: ReadRequest<'a>,
+    ) -> LocalBoxFuture<'a, Result<Response, Error>>;
+}
+
+/// Determines the scheme of a URL, treating anything without one as a local path.
+///
+/// A scheme of a single letter is the drive of a Windows path, such as `C:\mirror`.
+pub(crate) fn scheme(url: &str) -> &str {
+    let scheme = match url.find(':') {
+        Some(pos) => &url[..pos],
+        None => return "file",
+    };
+
+    let valid = scheme.len() > 1
+        && scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme.chars().all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c));
+
+    if valid {
+        scheme
+    } else {
+        "file"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn schemes() {
+        assert_eq!(scheme("https://example.com/file"), "https");
+        assert_eq!(scheme("data:,text"), "data");
+        assert_eq!(scheme("file:///srv/file"), "file");
+        assert_eq!(scheme("/srv/file"), "file");
+        assert_eq!(scheme("relative:name/file"), "relative");
+        assert_eq!(scheme("C:\\mirror\\file.iso"), "file");
+        assert_eq!(scheme("d:/mirror/file.iso"), "file");
+        assert_eq!(scheme("./a:b"), "file");
+    }
+}